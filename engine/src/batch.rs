@@ -0,0 +1,161 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::cli::{self, CLIPosition};
+use crate::error::Error;
+use crate::eval::{self, EvalTable};
+use crate::nimstring::{self, NimstringTable};
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::Path;
+use time::Instant;
+
+// Non-interactive analysis of many position files, printing one line of results per file,
+// either as a table for people to read or as CSV for other programs
+
+// Results for one position file, already formatted for output
+struct Row {
+    file: String,
+    size: String,
+    value: String,
+    best_move: String,
+    nimstring_value: String,
+    seconds: String,
+}
+
+const HEADINGS: [&str; 6] = ["File", "Size", "Value", "Best move", "NV", "Seconds"];
+const CSV_HEADINGS: [&str; 6] = ["file", "size", "value", "best_move", "nimstring_value", "seconds"];
+
+impl Row {
+    fn fields(self: &Row) -> [&str; 6] {
+        [&self.file, &self.size, &self.value, &self.best_move, &self.nimstring_value, &self.seconds]
+    }
+}
+
+// The files to analyse: files named directly, and the .pos files in directories, in name order
+fn position_files(paths: &[String]) -> Result<Vec<String>, Error> {
+    let mut result = Vec::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            result.push(path.clone());
+            continue;
+        }
+        let entries = fs::read_dir(path).map_err(
+            |e| Error::Io(format!("Could not read directory [{}]: {}", path, e)))?;
+        let mut files: Vec<String> = entries.filter_map(|e| e.ok()).map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().is_some_and(|x| x == "pos"))
+            .map(|p| p.to_string_lossy().into_owned()).collect();
+        files.sort();
+        result.append(&mut files);
+    }
+    Ok(result)
+}
+
+// Evaluate the position in a file and work out its nimstring value
+fn analyse(filename: &str, threads: usize) -> Result<Row, Error> {
+    let f = File::open(filename).map_err(
+        |e| Error::Io(format!("Could not open file [{}]: {}", filename, e)))?;
+    let pos = cli::parse_position(BufReader::new(f))?;
+    let start_time = Instant::now();
+    let (value, best_move) = eval::eval_parallel_cached(&pos, &mut EvalTable::default(), threads);
+    let (nimstring_value, _) = nimstring::calc_value_with_moves_parallel_cached(
+        &pos, &mut NimstringTable::default(), threads);
+    let seconds = start_time.elapsed().as_seconds_f64();
+    let sizes: Vec<String> = pos.dimensions().chunks(2).map(|d| format!("{}x{}", d[0], d[1])).collect();
+    Ok(Row{
+        file: filename.to_string(),
+        size: sizes.join("+"),
+        value: value.to_string(),
+        best_move: best_move.map_or("-".to_string(), |m| pos.format_move(m)),
+        nimstring_value: nimstring_value.to_string(),
+        seconds: format!("{:.3}", seconds),
+    })
+}
+
+fn csv_line(fields: &[&str]) -> String {
+    let quoted: Vec<String> = fields.iter().map(|f| {
+        if f.contains(',') || f.contains('"') {
+            format!("\"{}\"", f.replace('"', "\"\""))
+        } else {
+            f.to_string()
+        }
+    }).collect();
+    quoted.join(",")
+}
+
+// Table columns are as wide as the longest file name, or wide enough for typical values
+fn table_line(fields: &[&str], file_width: usize) -> String {
+    format!("{:<fw$}  {:<16}  {:>6}  {:<10}  {:<4}  {:>8}",
+            fields[0], fields[1], fields[2], fields[3], fields[4], fields[5], fw = file_width)
+        .trim_end().to_string()
+}
+
+// Analyse every position file found in the given paths, writing the results as they are found.
+// Files which cannot be analysed are reported on standard error and skipped.
+// Returns the number of files skipped.
+pub fn run<W: Write>(paths: &[String], csv: bool, threads: usize, out: &mut W) -> Result<usize, Error> {
+    let files = position_files(paths)?;
+    let file_width = files.iter().map(|f| f.len()).chain([HEADINGS[0].len()]).max().unwrap();
+    let io_error = |e: std::io::Error| Error::Io(format!("Could not write results: {}", e));
+    let heading = if csv { csv_line(&CSV_HEADINGS) } else { table_line(&HEADINGS, file_width) };
+    writeln!(out, "{}", heading).map_err(io_error)?;
+    let mut failures = 0;
+    for file in &files {
+        match analyse(file, threads) {
+            Ok(row) => {
+                let line = if csv { csv_line(&row.fields()) } else { table_line(&row.fields(), file_width) };
+                writeln!(out, "{}", line).and_then(|_| out.flush()).map_err(io_error)?;
+            },
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failures += 1;
+            },
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!("a,\"b,c\",\"say \"\"hi\"\"\"", csv_line(&["a", "b,c", "say \"hi\""]));
+        let row = Row{file: "p50bl.pos".to_string(), size: "3x2".to_string(), value: "-2".to_string(),
+                      best_move: "0 1 t".to_string(), nimstring_value: "*1".to_string(), seconds: "0.012".to_string()};
+        assert_eq!("File       Size               Value  Best move   NV     Seconds", table_line(&HEADINGS, 9));
+        assert_eq!("p50bl.pos  3x2                   -2  0 1 t       *1       0.012", table_line(&row.fields(), 9));
+        assert_eq!("p50bl.pos,3x2,-2,0 1 t,*1,0.012", csv_line(&row.fields()));
+    }
+
+    #[test]
+    fn files_in_directories() {
+        let dir = std::env::temp_dir().join(format!("dabengine-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["b.pos", "a.pos", "notes.txt"] {
+            fs::write(dir.join(name), "1 1\n").unwrap();
+        }
+        let dir_name = dir.to_string_lossy().into_owned();
+        let files = position_files(&[dir_name, "x.txt".to_string()]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let expected: Vec<String> = vec!(dir.join("a.pos").to_string_lossy().into_owned(),
+                                         dir.join("b.pos").to_string_lossy().into_owned(), "x.txt".to_string());
+        assert_eq!(expected, files);
+    }
+}
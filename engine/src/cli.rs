@@ -17,44 +17,87 @@
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::game::{Move, SimplePosition, CompoundPosition, Side, CPosMove};
+use crate::endgame;
+use crate::error::Error;
+use crate::game::{Move, SimplePosition, CompoundPosition, Side, CPosMove, Game, GameMove, GameResult};
 use crate::nimstring;
-use crate::eval::{self, EvaluablePosition};
+use crate::parity;
+use crate::eval::{self, EvaluablePosition, EvalTable};
+use crate::splitter::SplittablePosition;
+use crate::structure;
+use crate::svg::{self, Diagram, Drawable, GridMove};
+use crate::ttable::TranspositionTable;
 
 use std::fmt::Display;
 use std::hash::Hash;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::fs::File;
 use regex::Regex;
-use time::Instant;
+use time::{Instant, OffsetDateTime};
 
 #[derive(PartialEq)]
 #[derive(Debug)]
 enum Command<M> {
     MakeMove(M),
     UndoMove(M),
+    UndoLast,
+    Redo,
     CalcNimstringValue,
     Evaluate,
+    EvaluateAll,
+    ControlledValue,
+    PrintPV(Option<usize>),
+    AnalyseStructure,
+    AnalyseParity,
+    PrintTableStats,
+    SetTableMemory(usize),
+    SetThreads(usize),
+    Save(String),
+    Load(String),
+    ExportSvg(svg::Style, String),
     PrintHelp,
     Quit,
 }
 
-impl <M: Copy + Display + Eq + Hash> Command<M> {
-    fn execute<P>(self: &Command<M>, pos: &mut P)
+// Search results kept between commands, so that analysing one position
+// can reuse the work done on earlier ones, and the number of threads to search with
+struct Session<M> {
+    eval_table: EvalTable<M>,
+    nimstring_table: nimstring::NimstringTable,
+    threads: usize,
+}
+
+impl<M: Copy> Session<M> {
+    fn new() -> Session<M> {
+        Session{eval_table: TranspositionTable::default(), nimstring_table: TranspositionTable::default(), threads: 1}
+    }
+}
+
+impl <M: Copy + Display + Eq + Hash + Send + Sync + GridMove> Command<M> {
+    fn execute<P>(self: &Command<M>, game: &mut Game<M, P>, session: &mut Session<M>)
     where P: CLIPosition<M> {
         match self {
-            &Command::MakeMove(m) => {
-                if pos.is_legal_move(m) {
-                    pos.make_move(m);
-                }
-                else {
-                    println!("Not a legal move: {}", m);
+            &Command::MakeMove(_) | &Command::UndoMove(_) | &Command::UndoLast | &Command::Redo => {
+                if let Err(e) = self.apply(game) {
+                    println!("{}", e);
                 }
             },
-            &Command::UndoMove(m) => { pos.undo_move(m); },
             &Command::CalcNimstringValue => {
-                let (val, per_move) = nimstring::calc_value_with_moves(pos);
+                let pos = game.position();
+                let (val, per_move) = nimstring::calc_value_with_moves_parallel_cached(
+                    pos, &mut session.nimstring_table, session.threads);
                 println!("Position value is {}", val);
+                if val.is_loony() {
+                    let part_values = nimstring::calc_part_values_cached(pos, &mut session.nimstring_table);
+                    for (i, v) in part_values.iter().enumerate() {
+                        if let &nimstring::Value::Loony{controlled_value, ..} = v {
+                            match controlled_value {
+                                Some(cv) => println!("Component {} is loony, controlled value {}", i, cv),
+                                None => println!("Component {} is loony, controlled value unknown", i),
+                            }
+                        }
+                    }
+                }
                 let mut moves: Vec<&M> = per_move.keys().collect();
                 pos.sort_moves(&mut moves);
                 for &m in &moves {
@@ -62,33 +105,161 @@ impl <M: Copy + Display + Eq + Hash> Command<M> {
                 }
             },
             &Command::Evaluate => {
-                let (val, best_move) = eval::eval(pos);
+                let (val, best_move) = eval::eval_parallel_cached(
+                    game.position(), &mut session.eval_table, session.threads);
                 if let Some(best_move) = best_move {
                     println!("V(P) = {}, best move {}", val, best_move);
                 } else {
                     println!("V(P) = {}", val);
                 }
             },
-            &Command::PrintHelp => { print_help(pos); },
+            &Command::EvaluateAll => {
+                let pos = game.position();
+                let (val, per_move) = eval::eval_with_moves_parallel_cached(
+                    pos, &mut session.eval_table, session.threads);
+                println!("V(P) = {}", val);
+                let mut moves: Vec<&M> = per_move.keys().collect();
+                pos.sort_moves(&mut moves);
+                for &m in &moves {
+                    let m_val = per_move[m];
+                    if m_val < val {
+                        println!("{} {} (loses {})", m, m_val, val - m_val);
+                    } else {
+                        println!("{} {}", m, m_val);
+                    }
+                }
+            },
+            &Command::ControlledValue => {
+                // Only search for V(P) once CV(P) is known, when the position is an endgame
+                let pos = game.position();
+                match endgame::controlled_value(pos) {
+                    Some(cv) => {
+                        let (val, _) = eval::eval_parallel_cached(pos, &mut session.eval_table, session.threads);
+                        let optimal = if cv == val { "optimal" } else { "not optimal" };
+                        println!("V(P) = {}, CV(P) = {}, so keeping control is {}", val, cv, optimal);
+                    },
+                    None => println!("CV(P) unknown as not every component is a chain or loop"),
+                }
+            },
+            &Command::PrintPV(max_moves) => {
+                let (val, _) = eval::eval_parallel_cached(game.position(), &mut session.eval_table, session.threads);
+                let pv = eval::principal_variation(
+                    game.position(), &mut session.eval_table, max_moves.unwrap_or(usize::MAX));
+                println!("V(P) = {}", val);
+                print!("{}", format_pv(game, &pv));
+            },
+            &Command::AnalyseStructure => {
+                println!("{}", structure::analyse(game.position()));
+            },
+            &Command::AnalyseParity => {
+                match game.position().as_simple() {
+                    Some(pos) => println!("{}", parity::analyse(pos)),
+                    None => println!("Parity analysis needs a position on a single board"),
+                }
+            },
+            &Command::PrintTableStats => {
+                println!("eval table: {}", session.eval_table.stats());
+                println!("nv table: {}", session.nimstring_table.stats());
+            },
+            &Command::SetTableMemory(mb) => {
+                match mb.checked_mul(1 << 20) {
+                    Some(max_bytes) => {
+                        session.eval_table.set_max_bytes(max_bytes);
+                        session.nimstring_table.set_max_bytes(max_bytes);
+                        // Tables round their capacity down to a power of two
+                        println!("eval table limited to {} bytes", session.eval_table.max_bytes());
+                        println!("nv table limited to {} bytes", session.nimstring_table.max_bytes());
+                    },
+                    None => println!("Table size of {} MB is too large", mb),
+                }
+            },
+            &Command::SetThreads(n) => {
+                session.threads = n.max(1);
+                println!("Searching with {} thread(s)", session.threads);
+            },
+            Command::Save(filename) => {
+                match save_game(game, filename) {
+                    Ok(()) => println!("Saved game to {}", filename),
+                    Err(e) => println!("{}", e),
+                }
+            },
+            Command::Load(filename) => {
+                match load_game(filename, session) {
+                    Ok(loaded) => {
+                        *game = loaded;
+                        println!("Loaded game from {}", filename);
+                    },
+                    Err(e) => println!("{}", e),
+                }
+            },
+            Command::ExportSvg(style, filename) => {
+                let diagram = Diagram::from_game(game, *style);
+                match std::fs::write(filename, diagram.to_string()) {
+                    Ok(()) => println!("Wrote diagram to {}", filename),
+                    Err(e) => println!("Could not write file [{}]: {}", filename, e),
+                }
+            },
+            &Command::PrintHelp => { print_help(game.position()); },
             &Command::Quit => { println!("Bye bye!"); },
         }
     }
 }
 
-trait CLIPosition<M> : EvaluablePosition<M> + Display + Clone {
+impl <M: Copy + Display> Command<M> {
+    // Apply a command which changes the game record, or report why it cannot be applied.
+    // Other commands have no effect on the game.
+    fn apply<P>(self: &Command<M>, game: &mut Game<M, P>) -> Result<(), Error>
+    where P: CLIPosition<M> {
+        match *self {
+            Command::MakeMove(m) => game.make_move(m).map(|_| ()),
+            // Only the last move can be taken back, to keep the game record consistent
+            Command::UndoMove(m) => game.undo_move(m),
+            Command::UndoLast => game.undo().map(|_| ()).ok_or(Error::NothingToUndo),
+            Command::Redo => game.redo().map(|_| ()).ok_or(Error::NothingToRedo),
+            _ => Ok(()),
+        }
+    }
+
+    fn changes_game(self: &Command<M>) -> bool {
+        matches!(*self, Command::MakeMove(_) | Command::UndoMove(_) | Command::UndoLast | Command::Redo)
+    }
+}
+
+pub(crate) trait CLIPosition<M> : EvaluablePosition<M> + SplittablePosition<M, Part = SimplePosition> + Drawable + Display + Clone + Sync {
+    // Create a new game from the dimensions on the first line of a game file
+    fn from_dimensions(dims: &[usize]) -> Result<Self, String>;
+    // Dimensions from which from_dimensions would recreate the board
+    fn dimensions(&self) -> Vec<usize>;
     fn parse_move(&self, input: &str) -> Result<M, String>;
+    // Express a move in the syntax understood by parse_move
+    fn format_move(&self, m: M) -> String;
     // Tell the user how to express a move
     fn move_cmd_help(&self, verb: &str) -> String;
     // Sort moves into the optimal order for display
     fn sort_moves(&self, moves: &mut Vec<&M>);
+    // The position as a single board, if it is one
+    fn as_simple(&self) -> Option<&SimplePosition>;
 }
 
 impl CLIPosition<Move> for SimplePosition {
+    fn from_dimensions(dims: &[usize]) -> Result<SimplePosition, String> {
+        match dims {
+            &[width, height] => SimplePosition::try_new_game(width, height).map_err(|e| e.to_string()),
+            _ => Err(format!("Expected 2 dimensions, found: {:?}", dims)),
+        }
+    }
+
+    fn dimensions(self: &SimplePosition) -> Vec<usize> {
+        vec!(self.width(), self.height())
+    }
+
     fn parse_move(self: &SimplePosition, input: &str) -> Result<Move, String> {
         let move_re = Regex::new(r"^(\d+) (\d+) ([a-zA-Z]+)$").unwrap();
         if let Some(caps) = move_re.captures(&input) {
-            let x = caps[1].parse::<usize>().unwrap();
-            let y = caps[2].parse::<usize>().unwrap();
+            let coord = |s: &str| s.parse::<usize>().map_err(
+                |e| format!("Cannot parse coordinate [{}] in [{}]: {}", s, input, e));
+            let x = coord(&caps[1])?;
+            let y = coord(&caps[2])?;
             let side_s = caps[3].to_string();
             match parse_side(&side_s) {
                 Some(side) => Ok(Move{x: x, y: y, side: side}),
@@ -99,6 +270,16 @@ impl CLIPosition<Move> for SimplePosition {
         }
     }
 
+    fn format_move(self: &SimplePosition, m: Move) -> String {
+        let side = match m.side {
+            Side::Top => "t",
+            Side::Left => "l",
+            Side::Bottom => "b",
+            Side::Right => "r",
+        };
+        format!("{} {} {}", m.x, m.y, side)
+    }
+
     fn move_cmd_help(self: &SimplePosition, verb: &str) -> String {
         format!("x y t/l/b/r - {} move (x,y) top/left/bottom/right", verb)
     }
@@ -106,9 +287,25 @@ impl CLIPosition<Move> for SimplePosition {
     fn sort_moves(self: &SimplePosition, moves: &mut Vec<&Move>) {
         moves.sort_by(|a, b| a.y.cmp(&b.y).then(a.x.cmp(&b.x)).then(a.side.cmp(&b.side)));
     }
+
+    fn as_simple(self: &SimplePosition) -> Option<&SimplePosition> {
+        Some(self)
+    }
 }
 
 impl CLIPosition<CPosMove> for CompoundPosition {
+    fn from_dimensions(dims: &[usize]) -> Result<CompoundPosition, String> {
+        if dims.len() < 2 || dims.len() % 2 != 0 {
+            return Err(format!("Expected an even number of dimensions and at least 2, found: {:?}", dims));
+        }
+        let parts = dims.chunks(2).map(SimplePosition::from_dimensions).collect::<Result<_, _>>()?;
+        Ok(CompoundPosition::new(parts))
+    }
+
+    fn dimensions(self: &CompoundPosition) -> Vec<usize> {
+        self.parts.iter().flat_map(|p| p.dimensions()).collect()
+    }
+
     fn parse_move(self: &CompoundPosition, input: &str) -> Result<CPosMove, String> {
         // If there's only one part, let the user use the SimplePosition format for short
         if self.parts.len() == 1 {
@@ -118,21 +315,28 @@ impl CLIPosition<CPosMove> for CompoundPosition {
         }
         let move_re = Regex::new(r"^(\d+) (.*)$").unwrap();
         if let Some(caps) = move_re.captures(&input) {
-            let p = caps[1].parse::<usize>().unwrap();
+            let p = caps[1].parse::<usize>().map_err(
+                |e| format!("Cannot parse part number [{}] in [{}]: {}", &caps[1], input, e))?;
             let rest = &caps[2];
             if p >= self.parts.len() {
                 return Err(format!("Part {} out of bounds (count={})", p, self.parts.len()));
             }
-            if let Ok(m) = self.parts[p].parse_move(rest) {
-                Ok(CPosMove{part: p, m: m})
-            } else {
-                Err(format!("Could not parse [{}] as SimplePosition move", rest))
-            }
+            self.parts[p].parse_move(rest).map(|m| CPosMove{part: p, m: m}).map_err(
+                |e| format!("Could not parse [{}] as SimplePosition move: {}", rest, e))
         } else {
             Err(format!("Could not extract move from [{}]", input))
         }
     }
 
+    fn format_move(self: &CompoundPosition, m: CPosMove) -> String {
+        let part_move = self.parts[m.part].format_move(m.m);
+        if self.parts.len() == 1 {
+            part_move
+        } else {
+            format!("{} {}", m.part, part_move)
+        }
+    }
+
     fn move_cmd_help(self: &CompoundPosition, verb: &str) -> String {
         format!("[p] x y t/l/b/r - {} move (x,y) top/left/bottom/right in part p", verb)
     }
@@ -143,6 +347,13 @@ impl CLIPosition<CPosMove> for CompoundPosition {
                 a.m.y.cmp(&b.m.y)).then(
                     a.m.side.cmp(&b.m.side)));
     }
+
+    fn as_simple(self: &CompoundPosition) -> Option<&SimplePosition> {
+        match self.parts.as_slice() {
+            [part] => Some(part),
+            _ => None,
+        }
+    }
 }
 
 fn print_help<M, P>(pos: &P)
@@ -150,13 +361,26 @@ where P: CLIPosition<M> {
     println!("Available commands:");
     println!("{}", pos.move_cmd_help("make"));
     println!("u {}", pos.move_cmd_help("undo"));
+    println!("u - undo the last move");
+    println!("redo - make the last undone move again");
     println!("nv - calculate Nimstring value of current position");
     println!("eval - evaluate the current position");
+    println!("evalall - evaluate every move in the current position");
+    println!("cv - compare the value of a position of chains and loops with its controlled value");
+    println!("pv [n] - show the best line of play from the current position, up to n moves");
+    println!("structure - classify the chains and loops in the current position");
+    println!("parity - count long chains and list moves which flip or keep their parity");
+    println!("stats - show how well the eval and nv tables are being reused");
+    println!("hash n - limit the eval and nv tables to n MB each");
+    println!("threads n - search with n threads");
+    println!("save f - save the game to file f");
+    println!("load f - replace the game with one loaded from file f");
+    println!("svg [snc] f - draw the position to SVG file f, as strings-and-coins if snc is given");
     println!("help - print this help message");
     println!("quit/exit - exit program");
 }
 
-fn parse_side(side_s: &str) -> Option<Side> {
+pub(crate) fn parse_side(side_s: &str) -> Option<Side> {
     if side_s == "l" || side_s == "left" { Some(Side::Left) }
     else if side_s == "r" || side_s == "right" { Some(Side::Right) }
     else if side_s == "t" || side_s == "top" { Some(Side::Top) }
@@ -165,6 +389,17 @@ fn parse_side(side_s: &str) -> Option<Side> {
 }
 
 fn parse_command<M, P: CLIPosition<M>>(input: &str, pos: &P) -> Result<Command<M>, String> {
+    // File names keep their case
+    let file_re = Regex::new(r"^(?i:(save|load)) (.+)$").unwrap();
+    if let Some(caps) = file_re.captures(input) {
+        let filename = caps[2].to_string();
+        return Ok(if caps[1].eq_ignore_ascii_case("save") { Command::Save(filename) } else { Command::Load(filename) });
+    }
+    let svg_re = Regex::new(r"^(?i:svg)( (?i:snc))? (.+)$").unwrap();
+    if let Some(caps) = svg_re.captures(input) {
+        let style = if caps.get(1).is_some() { svg::Style::StringsAndCoins } else { svg::Style::DotsAndBoxes };
+        return Ok(Command::ExportSvg(style, caps[2].to_string()));
+    }
     let input = input.to_lowercase();
     if "nv" == input {
         return Ok(Command::CalcNimstringValue);
@@ -172,9 +407,48 @@ fn parse_command<M, P: CLIPosition<M>>(input: &str, pos: &P) -> Result<Command<M
     if "eval" == input {
         return Ok(Command::Evaluate);
     }
+    if "evalall" == input {
+        return Ok(Command::EvaluateAll);
+    }
+    if "cv" == input {
+        return Ok(Command::ControlledValue);
+    }
+    if "pv" == input {
+        return Ok(Command::PrintPV(None));
+    }
+    let pv_re = Regex::new(r"^pv (\d+)$").unwrap();
+    if let Some(caps) = pv_re.captures(&input) {
+        return caps[1].parse::<usize>().map(|n| Command::PrintPV(Some(n))).map_err(
+            |e| format!("Cannot parse move count from [{}]: {}", input, e));
+    }
+    if "structure" == input {
+        return Ok(Command::AnalyseStructure);
+    }
+    if "parity" == input {
+        return Ok(Command::AnalyseParity);
+    }
+    if "stats" == input {
+        return Ok(Command::PrintTableStats);
+    }
     if "help" == input {
         return Ok(Command::PrintHelp);
     }
+    let hash_re = Regex::new(r"^hash (\d+)$").unwrap();
+    if let Some(caps) = hash_re.captures(&input) {
+        return caps[1].parse::<usize>().map(Command::SetTableMemory).map_err(
+            |e| format!("Cannot parse table size from [{}]: {}", input, e));
+    }
+    let threads_re = Regex::new(r"^threads (\d+)$").unwrap();
+    if let Some(caps) = threads_re.captures(&input) {
+        return caps[1].parse::<usize>().map(Command::SetThreads).map_err(
+            |e| format!("Cannot parse thread count from [{}]: {}", input, e));
+    }
+    if "u" == input || "undo" == input {
+        return Ok(Command::UndoLast);
+    }
+    if "redo" == input {
+        return Ok(Command::Redo);
+    }
     if "exit" == input || "quit" == input {
         return Ok(Command::Quit);
     }
@@ -195,9 +469,14 @@ fn get_next_command<M, P>(pos: &P) -> Command<M>
 where P: CLIPosition<M> {
     loop {
         let mut input = String::new();
-        if let Err(error) = io::stdin().read_line(&mut input) {
-            println!("Error reading from standard input: {}", error);
-            continue;
+        match io::stdin().read_line(&mut input) {
+            // End of input
+            Ok(0) => return Command::Quit,
+            Ok(_) => {},
+            Err(error) => {
+                println!("Error reading from standard input: {}", error);
+                continue;
+            },
         }
         let input = input.trim();
         match parse_command(&input, pos) {
@@ -210,13 +489,96 @@ where P: CLIPosition<M> {
     }
 }
 
-fn main_loop_from<M, P>(pos: &mut P)
-where M: Copy + Display + Eq + Hash, P: CLIPosition<M> {
+// Describe a principal variation from the current position of a game, one line per turn,
+// followed by the number of boxes each player takes
+fn format_pv<M, P>(game: &Game<M, P>, pv: &[GameMove<M>]) -> String
+where M: Copy + Display, P: CLIPosition<M> {
+    let players = game.players();
+    let name = |player: usize| &players[(game.to_move() + player) % 2];
+    let mut result = String::new();
+    let mut boxes = [0, 0];
+    for turn in pv.chunk_by(|a, b| a.player == b.player) {
+        let player = turn[0].player;
+        let moves: Vec<String> = turn.iter().map(|gm| gm.m.to_string()).collect();
+        let captured: usize = turn.iter().map(|gm| gm.outcome.coins_captured).sum();
+        boxes[player] += captured;
+        result.push_str(&format!("{}: {}", name(player), moves.join(", ")));
+        if captured > 0 {
+            result.push_str(&format!(" ({} {})", captured, if captured == 1 { "box" } else { "boxes" }));
+        }
+        result.push('\n');
+    }
+    if pv.last().is_some_and(|gm| !gm.outcome.end_of_game) {
+        result.push_str("...\n");
+    }
+    result.push_str(&format!("{} takes {}, {} takes {}\n", name(0), boxes[0], name(1), boxes[1]));
+    result
+}
+
+// Describe the score, and whose turn it is or how the game ended
+fn game_status<M, P>(game: &Game<M, P>) -> String
+where M: Copy + Display, P: CLIPosition<M> {
+    let players = game.players();
+    let score = format!("{} {} - {} {}", players[0], game.score(0), game.score(1), players[1]);
+    match game.result() {
+        None => format!("{}, {} to move", score, players[game.to_move()]),
+        Some(GameResult::Win(p)) => format!("{}, {} wins", score, players[p]),
+        Some(GameResult::Draw) => format!("{}, game drawn", score),
+    }
+}
+
+// Have the engine make the best move for the player to move, in a game which is not over
+fn engine_move<M, P>(game: &mut Game<M, P>, session: &mut Session<M>) -> M
+where M: Copy + Display + PartialEq + Send + Sync, P: CLIPosition<M> {
+    let (_, best_move) = eval::eval_parallel_cached(game.position(), &mut session.eval_table, session.threads);
+    let m = best_move.expect("Engine found no move in a game which is not over");
+    game.make_move(m).expect("Engine chose an illegal move");
+    m
+}
+
+// Take back the engine's replies to the human's last move, and then that move,
+// so that it is the human's turn again
+fn undo_human_move<M, P>(game: &mut Game<M, P>, engine: usize) -> Result<(), Error>
+where M: Copy + Display, P: CLIPosition<M> {
+    let human_moves = game.moves().iter().filter(|gm| gm.player != engine).count();
+    if human_moves == 0 {
+        return Err(Error::NothingToUndo);
+    }
+    while game.moves().last().is_some_and(|gm| gm.player == engine) {
+        game.undo();
+    }
+    game.undo();
+    Ok(())
+}
+
+// Run the CLI, with the engine playing for one side if given
+fn main_loop_from<M, P>(game: &mut Game<M, P>, session: &mut Session<M>, engine: Option<usize>)
+where M: Copy + Display + Eq + Hash + Send + Sync + GridMove, P: CLIPosition<M> {
     loop {
-        println!("{}", pos);
-        let command = get_next_command(pos);
+        println!("{}", game.position());
+        println!("{}", game_status(game));
+        if let Some(engine) = engine {
+            if game.is_over() {
+                break;
+            }
+            // A capture keeps the turn, so the engine may move several times in a row
+            if game.to_move() == engine {
+                let m = engine_move(game, session);
+                println!("{} plays {}", game.players()[engine], m);
+                println!();
+                continue;
+            }
+        }
+        let command = get_next_command(game.position());
         let start_time = Instant::now();
-        command.execute(pos);
+        match (engine, &command) {
+            (Some(engine), &Command::UndoLast) => {
+                if let Err(e) = undo_human_move(game, engine) {
+                    println!("{}", e);
+                }
+            },
+            _ => command.execute(game, session),
+        }
         let elapsed = start_time.elapsed();
         if command == Command::Quit {
             break;
@@ -229,52 +591,126 @@ where M: Copy + Display + Eq + Hash, P: CLIPosition<M> {
     }
 }
 
-fn parse_position<R: BufRead>(reader: R) -> Result<CompoundPosition, String> {
-    let mut lines = reader.lines();
-    let size_spec = lines.next().map_or(
-        Err("No lines found".to_string()),
-        |l| l.map_err(|e| format!("Could not read first line: {}", e)))?;
+// Start a game between the two players of the CLI
+fn new_game<M, P>(pos: P) -> Game<M, P>
+where M: Copy + Display, P: CLIPosition<M> {
+    Game::new(pos, "A", "B")
+}
+
+// Read a game from the dimensions of its position on the first line, followed by
+// commands to play on it (typically moves), one per line.
+// Lines starting with # are comments. Those before the dimensions may hold metadata,
+// of which the names of the players are used.
+// Given a session, other commands are executed as they are read; without one,
+// only commands which change the game are accepted.
+fn parse_game<M, P, R>(reader: R, mut session: Option<&mut Session<M>>) -> Result<Game<M, P>, Error>
+where M: Copy + Display + Eq + Hash + Send + Sync + GridMove, P: CLIPosition<M>, R: BufRead {
+    let parse_error = |line: usize, message: String| Error::Parse{line: line, message: message};
+    let players_re = Regex::new(r"^#\s*Players:\s*(.+?)\s+vs\s+(.+?)\s*$").unwrap();
+    let mut players = ["A".to_string(), "B".to_string()];
+    let mut lines = reader.lines().enumerate().map(|(i, line)| {
+        line.map(|l| (i + 1, l)).map_err(|e| Error::Io(format!("Could not read line {}: {}", i + 1, e)))
+    });
+    let (size_line, size_spec) = loop {
+        let (line_number, line) = lines.next().ok_or(parse_error(1, "No lines found".to_string()))??;
+        if let Some(caps) = players_re.captures(&line) {
+            players = [caps[1].to_string(), caps[2].to_string()];
+        }
+        if line.trim().len() > 0 && !line.starts_with("#") {
+            break (line_number, line);
+        }
+    };
     let mut size_spec_parts: Vec<usize> = Vec::with_capacity(2);
     for part in size_spec.split(" ") {
         let dim = part.parse::<usize>().map_err(
-            |e| format!("Could not parse int from [{}]: {}", part, e))?;
+            |e| parse_error(size_line, format!("Could not parse int from [{}]: {}", part, e)))?;
         size_spec_parts.push(dim);
     }
-    if size_spec_parts.len() < 2 || size_spec_parts.len() % 2 != 0 {
-        return Err(format!(
-            "Expected an even number of dimensions and at least 2, found: {:?}", size_spec_parts));
-    }
-    let mut parts: Vec<SimplePosition> = Vec::with_capacity(size_spec_parts.len() / 2);
-    for pair in size_spec_parts.chunks(2) {
-        parts.push(SimplePosition::new_game(pair[0], pair[1]));
-    }
-    let mut pos = CompoundPosition::new(parts);
+    let pos = P::from_dimensions(&size_spec_parts).map_err(|e| parse_error(size_line, e))?;
+    let mut game = Game::new(pos, &players[0], &players[1]);
     for line in lines {
-        let line = line.map_err(|e| format!("Could not read line: {}", e))?;
+        let (line_number, line) = line?;
         if line.trim().len() == 0 || line.starts_with("#") {
             continue;
         }
-        let command = parse_command(&line, &pos)?;
-        command.execute(&mut pos);
+        let command = parse_command(&line, game.position()).map_err(|e| parse_error(line_number, e))?;
+        // Loading a file from within one could recurse without end
+        if let Command::Save(_) | Command::Load(_) = command {
+            return Err(parse_error(line_number, "Cannot load or save from within a game file".to_string()));
+        }
+        if command.changes_game() {
+            command.apply(&mut game).map_err(|e| parse_error(line_number, e.to_string()))?;
+        } else if let Some(session) = session.as_mut() {
+            command.execute(&mut game, session);
+        } else {
+            return Err(parse_error(line_number, format!("Only moves, undo and redo are allowed here, not [{}]", line)));
+        }
+    }
+    Ok(game)
+}
+
+// Write a game in the format read by parse_game: metadata comments, the dimensions
+// of the board and the moves made
+fn write_game<M, P, W>(game: &Game<M, P>, writer: &mut W) -> io::Result<()>
+where M: Copy + Display, P: CLIPosition<M>, W: Write {
+    let players = game.players();
+    writeln!(writer, "# Players: {} vs {}", players[0], players[1])?;
+    writeln!(writer, "# Date: {}", OffsetDateTime::now_utc().date())?;
+    if game.is_over() {
+        writeln!(writer, "# Result: {}", game_status(game))?;
+    }
+    let dims: Vec<String> = game.position().dimensions().iter().map(|d| d.to_string()).collect();
+    writeln!(writer, "{}", dims.join(" "))?;
+    for gm in game.moves() {
+        writeln!(writer, "{}", game.position().format_move(gm.m))?;
     }
-    Ok(pos)
+    Ok(())
+}
+
+fn save_game<M, P>(game: &Game<M, P>, filename: &str) -> Result<(), Error>
+where M: Copy + Display, P: CLIPosition<M> {
+    let io_error = |e: io::Error| Error::Io(format!("Could not write file [{}]: {}", filename, e));
+    let mut f = io::BufWriter::new(File::create(filename).map_err(io_error)?);
+    write_game(game, &mut f).and_then(|_| f.flush()).map_err(io_error)
+}
+
+fn load_game<M, P>(filename: &str, session: &mut Session<M>) -> Result<Game<M, P>, Error>
+where M: Copy + Display + Eq + Hash + Send + Sync + GridMove, P: CLIPosition<M> {
+    let f = File::open(filename).map_err(
+        |e| Error::Io(format!("Could not open file [{}]: {}", filename, e)))?;
+    parse_game(io::BufReader::new(f), Some(session))
+}
+
+// Read a position in the same format as parse_game, discarding the game record
+pub fn parse_position<R: BufRead>(reader: R) -> Result<CompoundPosition, Error> {
+    parse_game(reader, None).map(|game: Game<CPosMove, CompoundPosition>| game.into_position())
 }
 
 // Enter the main loop of the CLI from the start of the game
-pub fn main_loop_start(width: usize, height: usize) {
-    let mut pos = SimplePosition::new_game(width, height);
-    main_loop_from(&mut pos);
+pub fn main_loop_start(width: usize, height: usize) -> Result<(), Error> {
+    let mut game = new_game(SimplePosition::try_new_game(width, height)?);
+    main_loop_from(&mut game, &mut Session::new(), None);
+    Ok(())
+}
+
+// Play a game against the engine, which takes the side of the given player
+pub fn play_start(width: usize, height: usize, engine: usize) -> Result<(), Error> {
+    let mut game = new_game(SimplePosition::try_new_game(width, height)?);
+    println!("The engine plays as {}", game.players()[engine]);
+    main_loop_from(&mut game, &mut Session::new(), Some(engine));
+    if game.is_over() {
+        println!("Game over: {}", game_status(&game));
+    }
+    Ok(())
 }
 
 // Execute a given file of commands (which must have the dimensions of the position on the first line)
 // and then enter the CLI main loop.
-pub fn main_loop_file(filename: &str) {
-    let f = File::open(filename).expect(
-        format!("Could not open file [{}]", filename).as_str());
-    let reader = io::BufReader::new(f);
-    let mut pos = parse_position(reader).expect(
-        format!("Could not read position from [{}]", filename).as_str());
-    main_loop_from(&mut pos);
+pub fn main_loop_file(filename: &str) -> Result<(), Error> {
+    let mut session = Session::new();
+    let mut game: Game<CPosMove, CompoundPosition> = load_game(filename, &mut session)?;
+    main_loop_from(&mut game, &mut session, None);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -322,6 +758,14 @@ mod tests {
         assert_eq!(Command::UndoMove(CPosMove::new(1, 3, 2, Side::Top)), parse_command("u 1 3 2 Top", &pos).unwrap());
     }
 
+    #[test]
+    fn parse_undo_last_and_redo_cmds() {
+        let pos = SimplePosition::new_game(2, 2);
+        assert_eq!(Command::UndoLast, parse_command("u", &pos).unwrap());
+        assert_eq!(Command::UndoLast, parse_command("undo", &pos).unwrap());
+        assert_eq!(Command::Redo, parse_command("redo", &pos).unwrap());
+    }
+
     #[test]
     fn parse_nimstring_value_cmd() {
         let pos = SimplePosition::new_game(1, 1);
@@ -332,6 +776,91 @@ mod tests {
     fn parse_evaluate_cmd() {
         let pos = SimplePosition::new_game(1, 1);
         assert_eq!(Command::Evaluate, parse_command("eval", &pos).unwrap());
+        assert_eq!(Command::EvaluateAll, parse_command("evalall", &pos).unwrap());
+        assert_eq!(Command::ControlledValue, parse_command("cv", &pos).unwrap());
+    }
+
+    #[test]
+    fn parse_structure_cmd() {
+        let pos = SimplePosition::new_game(1, 1);
+        assert_eq!(Command::AnalyseStructure, parse_command("structure", &pos).unwrap());
+        assert_eq!(Command::AnalyseParity, parse_command("parity", &pos).unwrap());
+    }
+
+    #[test]
+    fn parse_table_cmds() {
+        let pos = SimplePosition::new_game(1, 1);
+        assert_eq!(Command::PrintTableStats, parse_command("stats", &pos).unwrap());
+        assert_eq!(Command::SetTableMemory(64), parse_command("hash 64", &pos).unwrap());
+        assert_eq!(Command::SetThreads(4), parse_command("threads 4", &pos).unwrap());
+        assert!(parse_command("hash 99999999999999999999999", &pos).is_err());
+
+        // A size too large to express in bytes leaves the tables as they were
+        let mut game = new_game(pos);
+        let mut session = Session::new();
+        let default_bytes = session.eval_table.max_bytes();
+        Command::SetTableMemory(usize::MAX).execute(&mut game, &mut session);
+        assert_eq!(default_bytes, session.eval_table.max_bytes());
+        Command::SetTableMemory(1).execute(&mut game, &mut session);
+        assert_eq!(true, session.eval_table.max_bytes() <= 1 << 20);
+        assert_eq!(true, session.nimstring_table.max_bytes() <= 1 << 20);
+    }
+
+    #[test]
+    fn engine_plays_whole_game() {
+        // With both sides played by the engine, the margin is the value of the start position
+        let mut game = new_game(SimplePosition::new_game(3, 2));
+        let mut session = Session::new();
+        let (val, _) = eval::eval(game.position());
+        while !game.is_over() {
+            let player = game.to_move();
+            let m = engine_move(&mut game, &mut session);
+            assert_eq!((m, player), (game.moves().last().unwrap().m, game.moves().last().unwrap().player));
+        }
+        assert_eq!(val, game.score(0) as isize - game.score(1) as isize);
+        assert_eq!(6, game.score(0) + game.score(1));
+    }
+
+    #[test]
+    fn undo_against_engine() {
+        let mut game = new_game(make_chain(3));
+        assert_eq!(Err(Error::NothingToUndo), undo_human_move(&mut game, 1));
+        // Human opens the chain; the engine takes all three coins
+        game.make_move(Move::new(0, 0, Side::Left)).unwrap();
+        let mut session = Session::new();
+        while !game.is_over() {
+            engine_move(&mut game, &mut session);
+        }
+        assert_eq!(3, game.score(1));
+        assert_eq!(Ok(()), undo_human_move(&mut game, 1));
+        assert_eq!(0, game.moves().len());
+        assert_eq!(0, game.to_move());
+        assert_eq!(Err(Error::NothingToUndo), undo_human_move(&mut game, 0));
+    }
+
+    #[test]
+    fn parse_pv_cmd() {
+        let pos = SimplePosition::new_game(1, 1);
+        assert_eq!(Command::PrintPV(None), parse_command("pv", &pos).unwrap());
+        assert_eq!(Command::PrintPV(Some(6)), parse_command("pv 6", &pos).unwrap());
+    }
+
+    #[test]
+    fn pv_format() {
+        // A opens one chain, and B double-deals to keep control
+        let mut game = new_game(double_chain(3));
+        let mut table = eval::EvalTable::default();
+        let pv = eval::principal_variation(game.position(), &mut table, usize::MAX);
+        assert_eq!("A: (0, 0) Left\n\
+                    B: (0, 0) Right, (2, 0) Right (1 box)\n\
+                    A: (1, 0) Right, (0, 1) Left (2 boxes)\n\
+                    B: (0, 1) Right, (1, 1) Right, (2, 1) Right (3 boxes)\n\
+                    A takes 2, B takes 4\n", format_pv(&game, &pv));
+
+        // Players are named relative to the player to move
+        game.make_move(Move::new(0, 0, Side::Left)).unwrap();
+        let pv = eval::principal_variation(game.position(), &mut table, 2);
+        assert_eq!("B: (0, 0) Right, (2, 0) Right (1 box)\n...\nB takes 1, A takes 0\n", format_pv(&game, &pv));
     }
 
     #[test]
@@ -370,24 +899,160 @@ mod tests {
         assert_eq!(true, expected.eq(&actual), "{}", actual);
     }
 
+    #[test]
+    fn parse_game_record() {
+        let input_str = vec!(
+            "2 1", "0 0 t", "0 0 b", "0 0 l", "0 0 r", "1 0 t", "1 0 b", "u", "u 1 0 b", "u", "redo"
+        ).join("\n");
+        let parsed: Result<Game<CPosMove, CompoundPosition>, _> = parse_game(Cursor::new(input_str), None);
+        // Undoing anything but the last move is refused
+        match parsed {
+            Err(Error::Parse{line, message: _}) => assert_eq!(9, line),
+            _ => panic!("Expected parse error"),
+        }
+
+        let input_str = vec!(
+            "2 1", "0 0 t", "0 0 b", "0 0 l", "0 0 r", "1 0 t", "1 0 b", "u", "u 1 0 t", "redo"
+        ).join("\n");
+        let game: Game<CPosMove, CompoundPosition> = parse_game(Cursor::new(input_str), None).unwrap();
+        assert_eq!(5, game.moves().len());
+        assert_eq!(1, game.score(1));
+        assert_eq!(0, game.to_move());
+    }
+
+    // Write a game out and read it back in
+    fn round_trip<M, P>(game: &Game<M, P>) -> (String, Game<M, P>)
+    where M: Copy + Display + Eq + Hash + Send + Sync + GridMove, P: CLIPosition<M> {
+        let mut buffer = Vec::new();
+        write_game(game, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let parsed = parse_game(Cursor::new(text.clone()), None).unwrap();
+        (text, parsed)
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut game = Game::new(SimplePosition::new_game(2, 1), "Alice", "Bob");
+        for m in vec!(Move::new(0, 0, Side::Top), Move::new(0, 0, Side::Bottom), Move::new(0, 0, Side::Left)) {
+            game.make_move(m).unwrap();
+        }
+        let (text, loaded) = round_trip(&game);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("# Players: Alice vs Bob", lines[0]);
+        assert_eq!(true, lines[1].starts_with("# Date: "));
+        assert_eq!(vec!("2 1", "0 0 t", "0 0 b", "0 0 l"), lines[2..]);
+        assert_eq!(&["Alice".to_string(), "Bob".to_string()], loaded.players());
+        assert_eq!(true, game.position().eq(loaded.position()));
+        assert_eq!(1, loaded.to_move());
+
+        // Finished games record the result
+        for m in vec!(Move::new(0, 0, Side::Right), Move::new(1, 0, Side::Top), Move::new(1, 0, Side::Bottom)) {
+            game.make_move(m).unwrap();
+        }
+        game.make_move(Move::new(1, 0, Side::Right)).unwrap();
+        let (text, loaded) = round_trip(&game);
+        assert_eq!(Some("# Result: Alice 0 - 2 Bob, Bob wins"), text.lines().nth(2));
+        assert_eq!((0, 2, true), (loaded.score(0), loaded.score(1), loaded.is_over()));
+
+        // Saved files are also readable as positions
+        let mut game = new_game(CompoundPosition::new(vec!(SimplePosition::new_game(3, 1), SimplePosition::new_game(4, 1))));
+        game.make_move(CPosMove::new(1, 0, 0, Side::Left)).unwrap();
+        let (text, loaded) = round_trip(&game);
+        assert_eq!(vec!("3 1 4 1", "1 0 0 l"), text.lines().skip(2).collect::<Vec<_>>());
+        assert_eq!(true, game.position().eq(loaded.position()));
+        assert_eq!(true, game.position().eq(&parse_position(Cursor::new(text)).unwrap()));
+
+        // A file for a compound position cannot be loaded into a simple game
+        let parsed: Result<Game<Move, SimplePosition>, _> = parse_game(Cursor::new("3 1 4 1"), None);
+        assert_eq!(Err(Error::Parse{line: 1, message: "Expected 2 dimensions, found: [3, 1, 4, 1]".to_string()}),
+                   parsed.map(|_| ()));
+    }
+
+    #[test]
+    fn save_and_load_files() {
+        let filename = std::env::temp_dir().join(format!("dabengine-save-{}.pos", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        let mut game = new_game(SimplePosition::new_game(3, 3));
+        game.make_move(Move::new(1, 1, Side::Right)).unwrap();
+        save_game(&game, filename).unwrap();
+        let loaded: Game<Move, SimplePosition> = load_game(filename, &mut Session::new()).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(true, game.position().eq(loaded.position()));
+        assert!(load_game::<Move, SimplePosition>(filename, &mut Session::new()).is_err());
+
+        // A file which loads itself is refused rather than read over and over
+        std::fs::write(filename, format!("1 1\nload {}\n", filename)).unwrap();
+        let loaded = load_game::<Move, SimplePosition>(filename, &mut Session::new());
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(true, matches!(loaded, Err(Error::Parse{line: 2, ..})));
+    }
+
+    #[test]
+    fn parse_file_cmds() {
+        let pos = SimplePosition::new_game(1, 1);
+        assert_eq!(Command::Save("Games/My Game.pos".to_string()), parse_command("save Games/My Game.pos", &pos).unwrap());
+        assert_eq!(Command::Load("X.pos".to_string()), parse_command("LOAD X.pos", &pos).unwrap());
+        assert_eq!(Command::ExportSvg(svg::Style::DotsAndBoxes, "Fig.svg".to_string()), parse_command("svg Fig.svg", &pos).unwrap());
+        assert_eq!(Command::ExportSvg(svg::Style::StringsAndCoins, "a b.svg".to_string()), parse_command("SVG snc a b.svg", &pos).unwrap());
+    }
+
     #[test]
     fn parse_position_errors() {
+        let parse_error = |line: usize| {
+            move |e: Error| match e {
+                Error::Parse{line: l, message} if l == line => message,
+                e => panic!("Expected parse error at line {}, got {:?}", line, e),
+            }
+        };
+
         let parsed = parse_position(Cursor::new(""));
-        assert!(parsed.is_err());
-        assert_eq!("No lines found", parsed.err().unwrap());
+        assert_eq!("No lines found", parsed.map_err(parse_error(1)).err().unwrap());
 
         let parsed = parse_position(Cursor::new("1"));
-        assert!(parsed.is_err());
-        assert!(parsed.err().unwrap().starts_with(
+        assert!(parsed.map_err(parse_error(1)).err().unwrap().starts_with(
             "Expected an even number of dimensions and at least 2"));
 
         let parsed = parse_position(Cursor::new("1 2 3"));
-        assert!(parsed.is_err());
-        assert!(parsed.err().unwrap().starts_with(
+        assert!(parsed.map_err(parse_error(1)).err().unwrap().starts_with(
             "Expected an even number of dimensions and at least 2"));
 
         let parsed = parse_position(Cursor::new("zxcv"));
-        assert!(parsed.is_err());
-        assert!(parsed.err().unwrap().contains("Could not parse int"));
+        assert!(parsed.map_err(parse_error(1)).err().unwrap().contains("Could not parse int"));
+
+        let parsed = parse_position(Cursor::new("100 1"));
+        assert!(parsed.map_err(parse_error(1)).err().unwrap().contains("exceeds maximum"));
+
+        let parsed = parse_position(Cursor::new("2 2\n# Comment\n0 0 t\n\n0 0 x"));
+        assert!(parsed.map_err(parse_error(5)).err().unwrap().contains("Cannot extract move"));
+
+        let parsed = parse_position(Cursor::new("2 2\n99999999999999999999999 0 t"));
+        assert!(parsed.map_err(parse_error(2)).err().unwrap().contains("Cannot parse part number"));
+
+        let parsed = parse_position(Cursor::new("2 2 1 1\n0 0 99999999999999999999999 t"));
+        assert!(parsed.map_err(parse_error(2)).err().unwrap().contains("Cannot parse coordinate"));
+
+        let parsed = parse_position(Cursor::new("2 2 1 1\n99999999999999999999999 0 0 t"));
+        assert!(parsed.map_err(parse_error(2)).err().unwrap().contains("Cannot parse part number"));
+
+        let parsed = parse_position(Cursor::new("2 2\n0 0 t\n0 0 t"));
+        assert_eq!("Not a legal move: Part 0: (0, 0) Top", parsed.map_err(parse_error(3)).err().unwrap());
+
+        let parsed = parse_position(Cursor::new("2 2\n0 0 t\n0 0 l\nu 0 0 t"));
+        assert!(parsed.map_err(parse_error(4)).err().unwrap().starts_with("Can only undo the last move"));
+
+        // Positions are read without running any other commands
+        let parsed = parse_position(Cursor::new("2 2\n0 0 t\neval"));
+        assert_eq!("Only moves, undo and redo are allowed here, not [eval]", parsed.map_err(parse_error(3)).err().unwrap());
+
+        let parsed = parse_position(Cursor::new("1 1\nload self.pos"));
+        assert_eq!("Cannot load or save from within a game file", parsed.map_err(parse_error(2)).err().unwrap());
+    }
+
+    #[test]
+    fn main_loop_file_missing() {
+        match main_loop_file("/nonexistent/file.pos") {
+            Err(Error::Io(message)) => assert!(message.contains("Could not open file")),
+            _ => panic!("Expected I/O error"),
+        }
     }
 }
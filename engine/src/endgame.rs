@@ -0,0 +1,429 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::game::SimplePosition;
+use crate::splitter::SplittablePosition;
+use crate::structure::{self, StructureKind};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Exact solver for simple-loony endgames, i.e. positions in which every component
+// is an independent chain or loop. Such a position is determined (up to symmetry)
+// by the multiset of its components, so it can be solved by dynamic programming
+// over multisets, which is far cheaper than searching the position itself.
+//
+// Play is restricted just as in the tree search in eval: free captures are always
+// taken, and when a chain or loop is offered, only capturing all or declining the
+// last two (or four) coins are considered (Theorems freecoins and loonyoptions).
+
+// One independent component. A chain of length n is a path of n coins, each end of
+// which is either attached to the ground or open (a coin which can be captured).
+// A chain whose ends are both open is what remains of a loop once it has been cut.
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+#[derive(PartialOrd)]
+#[derive(Ord)]
+enum Component {
+    Chain{length: usize, open_ends: usize},
+    Loop{length: usize},
+}
+
+// Result of a move on a single component: coins captured and what replaces it
+type Replacement = (usize, Vec<Component>);
+
+impl Component {
+    // Piece of a chain, of a given length and number of ends attached to the ground,
+    // left behind after cutting a string. A single coin with neither end grounded is captured.
+    fn piece(length: usize, grounded_ends: usize) -> Replacement {
+        if length == 1 && grounded_ends == 0 {
+            (1, vec!())
+        } else {
+            (0, vec!(Component::Chain{length: length, open_ends: 2 - grounded_ends}))
+        }
+    }
+
+    // All distinct results of cutting one string in this component
+    fn moves(self: &Component) -> Vec<Replacement> {
+        let mut result: Vec<Replacement> = Vec::new();
+        match *self {
+            Component::Loop{length} => {
+                result.push((0, vec!(Component::Chain{length: length, open_ends: 2})));
+            },
+            Component::Chain{length, open_ends} => {
+                // Put any grounded ends on the left
+                let grounded = 2 - open_ends;
+                let (left_grounded, right_grounded) = (grounded >= 1, grounded == 2);
+                if left_grounded {
+                    result.push(if length == 1 {
+                        Component::piece(1, grounded - 1)
+                    } else {
+                        (0, vec!(Component::Chain{length: length, open_ends: open_ends + 1}))
+                    });
+                }
+                // Strings between coins, which split the chain in two.
+                // Cutting the right-hand ground string is a mirror image of the left.
+                for i in 1..length {
+                    let (left_caps, mut left) = Component::piece(i, left_grounded as usize);
+                    let (right_caps, mut right) = Component::piece(length - i, right_grounded as usize);
+                    left.append(&mut right);
+                    result.push((left_caps + right_caps, left));
+                }
+            },
+        }
+        for r in result.iter_mut() {
+            r.1.sort();
+        }
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    // Whether this component is a loony offer in the sense of NimstringPosition::is_loony:
+    // a capturable coin next to a coin of valency 2 whose far side is not also capturable
+    fn is_loony(self: &Component) -> bool {
+        match *self {
+            Component::Chain{length, open_ends} => match length {
+                1 => false,
+                2 | 3 => open_ends == 1,
+                _ => open_ends > 0,
+            },
+            Component::Loop{..} => false,
+        }
+    }
+
+    // The captures available in this component
+    fn captures(self: &Component) -> Vec<Replacement> {
+        match *self {
+            Component::Chain{length, open_ends} if open_ends > 0 => {
+                if length == 1 {
+                    vec!((1, vec!()))
+                } else if length == 2 && open_ends == 2 {
+                    vec!((2, vec!()))
+                } else {
+                    vec!((1, vec!(Component::Chain{length: length - 1, open_ends: open_ends})))
+                }
+            },
+            _ => vec!(),
+        }
+    }
+
+    // The double-dealing reply to a loony offer: decline the two coins at the open end
+    fn ddeal(self: &Component) -> Replacement {
+        match *self {
+            Component::Chain{length: 2, ..} => (0, vec!(Component::Chain{length: 2, open_ends: 2})),
+            Component::Chain{length, open_ends} => {
+                // Whatever remains of the far end of the chain
+                let (caps, mut rest) = Component::piece(length - 2, 2 - open_ends);
+                debug_assert_eq!(0, caps);
+                rest.push(Component::Chain{length: 2, open_ends: 2});
+                rest.sort();
+                (0, rest)
+            },
+            Component::Loop{..} => panic!("Loops cannot be offered"),
+        }
+    }
+}
+
+fn is_loony(state: &[Component]) -> bool {
+    state.iter().any(|c| c.is_loony())
+}
+
+// Position after replacing the component at index i
+fn replace(state: &[Component], i: usize, replacement: &[Component]) -> Vec<Component> {
+    let mut result: Vec<Component> = state.iter().enumerate()
+        .filter(|&(j, _)| j != i).map(|(_, &c)| c).collect();
+    result.extend_from_slice(replacement);
+    result.sort();
+    result
+}
+
+// The moves worth considering, mirroring eval::moves_to_consider.
+// If keep_control is set, a player offered a loony component must keep control by
+// declining its last coins, unless it is all that is left. Half-hearted handouts are
+// then left out: they are never better than hard-hearted ones in real play (Theorem
+// halfheartedbad), but here the opponent would be forced to decline the coins.
+fn moves_to_consider(state: &[Component], keep_control: bool) -> Vec<(usize, Vec<Component>)> {
+    let loony = is_loony(state);
+    let mut offer: Option<(usize, Vec<Component>, Replacement)> = None;
+    for (i, c) in state.iter().enumerate() {
+        for (caps, replacement) in c.captures() {
+            let next = replace(state, i, &replacement);
+            if !loony || is_loony(&next) {
+                return vec!((caps, next));
+            }
+            offer = Some((caps, next, c.ddeal()));
+        }
+    }
+    if loony {
+        let (caps, next, (_, ddeal)) = offer.expect("Loony position must have a capture");
+        let i = state.iter().position(|c| c.is_loony()).unwrap();
+        let ddeal = (0, replace(state, i, &ddeal));
+        return if keep_control && state.len() > 1 { vec!(ddeal) } else { vec!((caps, next), ddeal) };
+    }
+
+    let mut result = Vec::new();
+    for (i, c) in state.iter().enumerate() {
+        // Identical components have identical moves
+        if i > 0 && state[i - 1] == *c {
+            continue;
+        }
+        for (caps, replacement) in c.moves() {
+            let half_hearted = *c == Component::Chain{length: 2, open_ends: 0}
+                && replacement == [Component::Chain{length: 2, open_ends: 1}];
+            if !(keep_control && half_hearted) {
+                result.push((caps, replace(state, i, &replacement)));
+            }
+        }
+    }
+    result
+}
+
+thread_local! {
+    static VALUES: RefCell<HashMap<Vec<Component>, isize>> = RefCell::new(HashMap::new());
+    static CONTROLLED_VALUES: RefCell<HashMap<Vec<Component>, isize>> = RefCell::new(HashMap::new());
+}
+
+// Value of a sorted multiset of components, for the player to move,
+// optionally with the players keeping control as for moves_to_consider
+fn state_value(state: &[Component], keep_control: bool) -> isize {
+    if state.is_empty() {
+        return 0;
+    }
+    let values = if keep_control { &CONTROLLED_VALUES } else { &VALUES };
+    if let Some(v) = values.with(|values| values.borrow().get(state).copied()) {
+        return v;
+    }
+    let value = moves_to_consider(state, keep_control).iter().map(|(caps, next)| {
+        if *caps > 0 {
+            *caps as isize + state_value(next, keep_control)
+        } else {
+            -state_value(next, keep_control)
+        }
+    }).max().unwrap();
+    values.with(|values| values.borrow_mut().insert(state.to_vec(), value));
+    value
+}
+
+// Describe one connected fragment as a chain or loop, if it is one
+fn component(part: &SimplePosition) -> Option<Component> {
+    let s = structure::classify(part);
+    let open_ends = (0..part.width()).flat_map(|x| (0..part.height()).map(move |y| (x, y)))
+        .filter(|&(x, y)| part.valency(x, y) == 1).count();
+    match (s.kind, s.open) {
+        (StructureKind::Loop, false) => Some(Component::Loop{length: s.length}),
+        (StructureKind::Other, _) => None,
+        _ => Some(Component::Chain{length: s.length, open_ends: open_ends}),
+    }
+}
+
+// The components of a position, sorted, if every one is a chain or loop
+fn state<M, P>(pos: &P) -> Option<Vec<Component>>
+where P: SplittablePosition<M, Part = SimplePosition> {
+    let mut state = pos.split().iter().map(component).collect::<Option<Vec<Component>>>()?;
+    state.sort();
+    Some(state)
+}
+
+// Value of a position for the player to move, if every component is a chain or loop
+pub fn value<M, P>(pos: &P) -> Option<isize>
+where P: SplittablePosition<M, Part = SimplePosition> {
+    state(pos).map(|state| state_value(&state, false))
+}
+
+// Controlled value CV(P) of a position for the player to move, if every component is
+// a chain or loop: its value if each player offered a loony component keeps control,
+// taking all but the last two coins (or four, for a loop) and declining those,
+// except in the last component, which they take in full.
+// In a loony endgame of long chains and loops, none of them opened, the player to move
+// must open each one, so this is minus the number of coins, plus four for each chain
+// and eight for each loop, less four if there are any chains or eight otherwise.
+pub fn controlled_value<M, P>(pos: &P) -> Option<isize>
+where P: SplittablePosition<M, Part = SimplePosition> {
+    state(pos).map(|state| state_value(&state, true))
+}
+
+// Indicate whether V(P) = CV(P), i.e. whether keeping control to the end is an optimal
+// strategy for the player in control, if every component is a chain or loop
+pub fn is_fixed_point<M, P>(pos: &P) -> Option<bool>
+where P: SplittablePosition<M, Part = SimplePosition> {
+    state(pos).map(|state| state_value(&state, false) == state_value(&state, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endgame::*;
+    use crate::examples::*;
+    use crate::game::{Position, CompoundPosition, CPosMove, Move, Side};
+    use std::cmp;
+
+    // Exhaustive search over every legal move, to compare against
+    fn search<M: Copy, P: Position<M>>(pos: &mut P, cache: &mut HashMap<usize, isize>) -> isize {
+        let moves = pos.legal_moves();
+        if moves.is_empty() {
+            return 0;
+        }
+        let key = pos.canonical_zhash();
+        if let Some(&v) = cache.get(&key) {
+            return v;
+        }
+        let mut result = isize::MIN;
+        for m in moves {
+            let outcome = pos.make_move(m);
+            let captures = outcome.coins_captured as isize;
+            let m_val = if captures > 0 {
+                captures + search(pos, cache)
+            } else {
+                -search(pos, cache)
+            };
+            pos.undo_move(m);
+            result = cmp::max(result, m_val);
+        }
+        cache.insert(key, result);
+        result
+    }
+
+    // Check the endgame value of a position, and of the positions after each of its moves
+    fn check<M: Copy, P>(pos: &mut P, name: &str)
+    where P: SplittablePosition<M, Part = SimplePosition> {
+        let mut cache = HashMap::new();
+        assert_eq!(Some(search(pos, &mut cache)), value(pos), "{}", name);
+        for m in pos.legal_moves() {
+            pos.make_move(m);
+            assert_eq!(Some(search(pos, &mut cache)), value(pos), "{} after a move", name);
+            pos.undo_move(m);
+        }
+    }
+
+    #[test]
+    fn chains() {
+        for length in 1..8 {
+            check(&mut make_chain(length), &format!("chain of {}", length));
+        }
+        for (size, count) in vec!((1, 4), (2, 3), (3, 2), (3, 4), (4, 2), (5, 2)) {
+            check(&mut multi_chains(size, count), &format!("{} chains of {}", count, size));
+        }
+    }
+
+    #[test]
+    fn loops() {
+        for width in 2..5 {
+            check(&mut double_loop(width), &format!("double loop of width {}", width));
+        }
+        let mut pos = CompoundPosition::new(vec!(double_loop(2), make_chain(3), make_chain(4)));
+        check(&mut pos, "loops and chains");
+    }
+
+    #[test]
+    fn one_long_multi_three_table() {
+        for (i, exp_val) in vec!(-4, -3, -2, -1, -2).into_iter().enumerate() {
+            let mut pos = one_long_multi_three(i, 4);
+            assert_eq!(Some(exp_val), value(&pos), "P_({}, 4)", i);
+            check(&mut pos, &format!("P_({}, 4)", i));
+        }
+    }
+
+    #[test]
+    fn controlled_values() {
+        // The tables for P_(i,4) and P_(i,10): keeping control stays optimal
+        // until there are as many 3-chains as coins in the long chain
+        for k in [4, 10] {
+            for i in 0..(k + 3) {
+                let pos = one_long_multi_three(i, k);
+                assert_eq!(Some(i as isize - k as isize), controlled_value(&pos), "P_({}, {})", i, k);
+                assert_eq!(Some(i < k), is_fixed_point(&pos), "P_({}, {})", i, k);
+            }
+        }
+        assert_eq!(Some(-4), controlled_value(&multi_chains(4, 3)));
+        // The drawn loony endgame of two 4-loops, and two 6-loops
+        assert_eq!((Some(0), Some(0)), (value(&double_loop(2)), controlled_value(&double_loop(2))));
+        assert_eq!(Some(-4), controlled_value(&double_loop(3)));
+        assert_eq!(Some(true), is_fixed_point(&double_loop(3)));
+
+        // Once a chain is opened, the player to move is in control
+        let mut pos = one_long_multi_three(2, 4);
+        pos.make_move(CPosMove::new(1, 0, 0, Side::Left));
+        assert_eq!(Some(2), controlled_value(&pos));
+        let mut pos = make_chain(5);
+        pos.make_move(Move{x: 0, y: 0, side: Side::Left});
+        assert_eq!(Some(5), controlled_value(&pos));
+    }
+
+    #[test]
+    fn worked_examples() {
+        // Exercise 3.1: after the solution, B and A take the ground strings in the
+        // top left, leaving B to open one of a 5-chain and a 4-chain.
+        // A keeps control, taking 7 coins to 2.
+        let mut pos = ex3p1();
+        assert_eq!(None, controlled_value(&pos));
+        for m in [Move::new(2, 1, Side::Bottom), Move::new(2, 0, Side::Top), Move::new(0, 2, Side::Left)] {
+            pos.make_move(m);
+        }
+        assert_eq!((Some(-5), Some(-5)), (value(&pos), controlled_value(&pos)));
+        assert_eq!(Some(true), is_fixed_point(&pos));
+
+        // Any other move loses, as B takes a ground string in the bottom right,
+        // turning it into a loop. Now A must open a 5-chain or a 4-loop.
+        let mut pos = ex3p1();
+        for m in [Move::new(2, 1, Side::Right), Move::new(2, 2, Side::Bottom),
+                  Move::new(2, 0, Side::Top), Move::new(0, 2, Side::Left)] {
+            pos.make_move(m);
+        }
+        assert_eq!((Some(-1), Some(-1)), (value(&pos), controlled_value(&pos)));
+        assert_eq!(Some(true), is_fixed_point(&pos));
+
+        // Exercise 3.12: after the solution, B sacrifices the joint at the top left,
+        // A takes two coins and hands out two, and B takes them and settles the bottom left.
+        // This leaves A to move in chains of 2, 4, 5 and 10 coins with the captures level,
+        // and A wins by 9 coins by giving up the 2-chain and then keeping control.
+        let mut pos = ex3p12();
+        assert_eq!(None, controlled_value(&pos));
+        for m in [Move::new(4, 0, Side::Bottom), Move::new(1, 0, Side::Right),
+                  Move::new(2, 0, Side::Bottom), Move::new(2, 1, Side::Bottom), Move::new(1, 2, Side::Bottom),
+                  Move::new(1, 2, Side::Right), Move::new(0, 4, Side::Left)] {
+            pos.make_move(m);
+        }
+        let lengths: Vec<usize> = structure::analyse(&pos).structures.iter().map(|s| s.length).collect();
+        assert_eq!(vec!(5, 2, 10, 4), lengths);
+        assert_eq!((Some(9), Some(9)), (value(&pos), controlled_value(&pos)));
+        assert_eq!(Some(true), is_fixed_point(&pos));
+
+        // P_(3,3) and P_(4,4) from the tables: B does better to take the first 3-chain
+        // than to keep control, so V(P) = -2 while CV(P) = 0
+        for k in [3, 4] {
+            let pos = one_long_multi_three(k, k);
+            assert_eq!((Some(-2), Some(0)), (value(&pos), controlled_value(&pos)), "P_({}, {})", k, k);
+            assert_eq!(Some(false), is_fixed_point(&pos));
+        }
+    }
+
+    #[test]
+    fn not_endgames() {
+        assert_eq!(None, value(&SimplePosition::new_game(2, 2)));
+        assert_eq!(None, value(&ex3p1()));
+        let mut pos = ex3p1();
+        pos.make_move(Move{x: 2, y: 1, side: Side::Bottom});
+        assert_eq!(None, value(&pos));
+        assert_eq!(Some(0), value(&SimplePosition::new_end_game(2, 2)));
+        assert_eq!(None, controlled_value(&SimplePosition::new_game(2, 2)));
+        assert_eq!(None, is_fixed_point(&ex3p12()));
+    }
+}
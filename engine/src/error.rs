@@ -0,0 +1,70 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::game::MAX_WIDTH;
+use std::error;
+use std::fmt;
+
+// Everything that can go wrong when the engine is given bad input.
+// Moves are recorded in their display form, so that the same type serves
+// for all kinds of position.
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub enum Error {
+    // A move which is not legal in the position it was applied to
+    IllegalMove(String),
+    // An attempt to undo a move whose string is still present
+    MoveNotMade(String),
+    // An attempt to undo a move other than the last one made in a game
+    NotLastMove(String),
+    NothingToUndo,
+    NothingToRedo,
+    // A capture which is not part of a loony offer, so has no double-dealing reply
+    NotLoony(String),
+    // A board wider than SimplePosition can represent
+    BoardTooWide(usize),
+    // A strings-and-coins graph which does not make sense
+    InvalidGraph(String),
+    // Unparseable input, with the (1-based) line number at which it was found
+    Parse { line: usize, message: String },
+    Io(String),
+    // A database file which is corrupt, or was written by an incompatible version
+    Database(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(self: &Error, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IllegalMove(ref m) => write!(f, "Not a legal move: {}", m),
+            Error::MoveNotMade(ref m) => write!(f, "Cannot undo a move which has not been made: {}", m),
+            Error::NotLastMove(ref m) => write!(f, "Can only undo the last move, which was {}", m),
+            Error::NothingToUndo => write!(f, "No moves to undo"),
+            Error::NothingToRedo => write!(f, "No moves to redo"),
+            Error::NotLoony(ref m) => write!(f, "No double-dealing move corresponding to {}", m),
+            Error::BoardTooWide(width) => write!(f, "Board width {} exceeds maximum of {}", width, MAX_WIDTH),
+            Error::InvalidGraph(ref message) => write!(f, "{}", message),
+            Error::Parse{line, ref message} => write!(f, "Line {}: {}", line, message),
+            Error::Io(ref message) => write!(f, "{}", message),
+            Error::Database(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -16,70 +16,99 @@
     You should have received a copy of the GNU Affero General Public License
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
-use crate::game::{Move, Position, SimplePosition, CompoundPosition, Side, CPosMove};
+use crate::endgame;
+use crate::error::Error;
+use crate::game::{Move, Position, SimplePosition, CompoundPosition, Side, CPosMove, GameMove};
 use crate::nimstring::{self, NimstringPosition};
+use crate::ttable::{Cache, ShardedTable, TranspositionTable, SHARDS_PER_THREAD};
 use std::collections::HashMap;
-use std::isize;
+use std::hash::Hash;
+use std::mem;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+use std::thread;
 
 pub trait EvaluablePosition<M> : NimstringPosition<M> {
-    // Given a loony position and the capture, find the corresponding double-dealing move.
-    // Behaviour on a non-loony position is undefined.
-    fn find_ddeal_move(&self, m: M) -> M;
+    // Given a loony position and the capture, find the corresponding double-dealing move,
+    // or report an error if the capture is not part of a loony offer.
+    fn try_find_ddeal_move(&self, m: M) -> Result<M, Error>;
+
+    // As try_find_ddeal_move, but panics if there is no double-dealing move.
+    fn find_ddeal_move(&self, m: M) -> M {
+        self.try_find_ddeal_move(m).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Whether the move would leave a coin capturable by the opponent.
+    // Only meaningful for moves which are not themselves captures.
+    fn is_sacrifice(&self, m: M) -> bool;
+
+    // Value of the position if it can be computed directly, without searching,
+    // as for endgames consisting only of independent chains and loops.
+    fn endgame_value(&self) -> Option<isize> {
+        None
+    }
 }
 
 impl EvaluablePosition<Move> for SimplePosition {
-    fn find_ddeal_move(self: &SimplePosition, capture: Move) -> Move {
+    fn try_find_ddeal_move(self: &SimplePosition, capture: Move) -> Result<Move, Error> {
+        let not_loony = || Error::NotLoony(capture.to_string());
+        if !self.is_legal_move(capture) || self.would_capture(capture) == 0 {
+            return Err(not_loony());
+        }
         // (capture.x, capture.y) might be the valency-1 coin or the valency-2 one
         let (v2_x, v2_y, excl_side) = if self.valency(capture.x, capture.y) == 1 {
-            let (x, y) = self.offset(capture.x, capture.y, capture.side).unwrap();
+            let (x, y) = self.offset(capture.x, capture.y, capture.side).ok_or_else(not_loony)?;
             (x, y, capture.side.opposite())
         } else {
             (capture.x, capture.y, capture.side)
         };
 
         if self.valency(v2_x, v2_y) != 2 {
-            panic!("Expected ({},{}) to have valency 2, found {} in {}",
-                   v2_x, v2_y, self.valency(v2_x, v2_y), self);
+            return Err(not_loony());
         }
 
         for s in Side::all_except(excl_side) {
             if self.is_legal_move(Move{x: v2_x, y: v2_y, side: s}) {
-                return Move{x: v2_x, y: v2_y, side: s};
+                return Ok(Move{x: v2_x, y: v2_y, side: s});
             }
         }
 
-        panic!("Could not find double-dealing move corresponding to {} in {}", capture, self);
+        Err(not_loony())
+    }
+
+    fn is_sacrifice(self: &SimplePosition, m: Move) -> bool {
+        // Cutting a string only lowers the valencies of the coins at either end
+        if self.valency(m.x, m.y) == 2 {
+            return true;
+        }
+        match self.offset(m.x, m.y, m.side) {
+            Some((x, y)) => self.valency(x, y) == 2,
+            None => false,
+        }
+    }
+
+    fn endgame_value(self: &SimplePosition) -> Option<isize> {
+        if self.has_joint() { None } else { endgame::value(self) }
     }
 }
 
 impl EvaluablePosition<CPosMove> for CompoundPosition {
-    fn find_ddeal_move(self: &CompoundPosition, capture: CPosMove) -> CPosMove {
-        CPosMove{part: capture.part, m: self.parts[capture.part].find_ddeal_move(capture.m)}
+    fn try_find_ddeal_move(self: &CompoundPosition, capture: CPosMove) -> Result<CPosMove, Error> {
+        match self.parts.get(capture.part) {
+            Some(p) => p.try_find_ddeal_move(capture.m)
+                .map(|m| CPosMove{part: capture.part, m: m})
+                .map_err(|_| Error::NotLoony(capture.to_string())),
+            None => Err(Error::NotLoony(capture.to_string())),
+        }
     }
-}
 
-// Evaluate a position given a set of moves to consider
-fn eval_moves<M, P>(pos: &mut P, moves: &Vec<M>,
-              cache: &mut HashMap<usize, (isize, M)>) -> (isize, Option<M>)
-where M: Copy, P: EvaluablePosition<M> {
-    if moves.is_empty() {
-        return (0, None);
+    fn is_sacrifice(self: &CompoundPosition, m: CPosMove) -> bool {
+        self.parts[m.part].is_sacrifice(m.m)
     }
-    let mut value = isize::MIN;
-    let mut best_move = moves[0];
-    for &m in moves {
-        let outcome = pos.make_move(m);
-        let sign = if outcome.coins_captured > 0 { 1 } else { -1 };
-        let (next_val, _) = eval_cache(pos, cache);
-        let sub_val = (outcome.coins_captured as isize) + sign * next_val;
-        pos.undo_move(m);
-        if sub_val > value {
-            value = sub_val;
-            best_move = m;
-        }
+
+    fn endgame_value(self: &CompoundPosition) -> Option<isize> {
+        if self.parts.iter().any(|p| p.has_joint()) { None } else { endgame::value(self) }
     }
-    cache.insert(pos.zhash(), (value, best_move));
-    (value, Some(best_move))
 }
 
 // Determine what moves deserve consideration in a given position
@@ -112,28 +141,421 @@ where M: Copy, P: EvaluablePosition<M> {
     }
 }
 
-fn eval_cache<M, P>(pos: &mut P, cache: &mut HashMap<usize, (isize, M)>) -> (isize, Option<M>)
+// Larger than the magnitude of any achievable position value, but small enough
+// that shifting a search window by a number of captured coins cannot overflow
+const INFINITY: isize = isize::MAX / 4;
+
+// How a cached value relates to the true value of the position: searches
+// with a narrow window may only establish a lower or an upper bound
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// Result of a previous search of a position, with the best move in canonical orientation
+#[derive(Clone, Copy, Debug)]
+pub struct CacheEntry<M> {
+    value: isize,
+    bound: Bound,
+    best_move: M,
+}
+
+// Table of search results which can be shared between calls to eval_cached
+pub type EvalTable<M> = TranspositionTable<CacheEntry<M>>;
+
+// Put the moves most likely to be best first, to maximise alpha-beta cut-offs:
+// captures, then safe moves, then sacrifices which keep the position non-loony,
+// then loony moves
+fn order_moves<M, P>(pos: &mut P, moves: &mut Vec<M>)
 where M: Copy, P: EvaluablePosition<M> {
-    if let Some(&(val, best_move)) = cache.get(&pos.zhash()) {
-        return (val, Some(best_move));
+    if moves.len() < 2 {
+        return;
+    }
+    let mut keyed: Vec<(usize, M)> = moves.iter().map(|&m| {
+        let priority = if pos.would_capture(m) > 0 {
+            0
+        } else if !pos.is_sacrifice(m) {
+            1
+        } else if !nimstring::would_be_loony(pos, m) {
+            2
+        } else {
+            3
+        };
+        (priority, m)
+    }).collect();
+    keyed.sort_by_key(|&(priority, _)| priority);
+    moves.clear();
+    moves.extend(keyed.into_iter().map(|(_, m)| m));
+}
+
+// Negamax alpha-beta search within the window (alpha, beta), returning a
+// fail-soft value and a move achieving it.
+// A capture keeps the same player to move, so the child is searched with the
+// window shifted by the coins captured rather than negated.
+// Once the stop flag is set the search unwinds, returning a meaningless value
+// and leaving the cache untouched.
+fn alpha_beta<M, P, C>(pos: &mut P, mut alpha: isize, mut beta: isize,
+                       cache: &mut C, stop: &AtomicBool) -> (isize, Option<M>)
+where M: Copy + PartialEq, P: EvaluablePosition<M>, C: Cache<CacheEntry<M>> {
+    if let Some(value) = pos.endgame_value() {
+        return (value, None);
+    }
+    let key = pos.canonical_zhash();
+    let mut cached_move = None;
+    if let Some(entry) = cache.get(key) {
+        let best_move = pos.move_from_canonical(entry.best_move);
+        match entry.bound {
+            Bound::Exact => return (entry.value, Some(best_move)),
+            Bound::Lower => alpha = alpha.max(entry.value),
+            Bound::Upper => beta = beta.min(entry.value),
+        }
+        if alpha >= beta {
+            return (entry.value, Some(best_move));
+        }
+        // When every move failed low, the move stored is no better than the others
+        if entry.bound == Bound::Lower {
+            cached_move = Some(best_move);
+        }
+    }
+
+    let mut moves = moves_to_consider(pos);
+    if moves.is_empty() {
+        return (0, None);
+    }
+    order_moves(pos, &mut moves);
+    // Try the best move from any previous search first
+    if let Some(cm) = cached_move {
+        if let Some(i) = moves.iter().position(|&m| m == cm) {
+            moves[..=i].rotate_right(1);
+        }
     }
 
-    let moves = moves_to_consider(pos);
-    eval_moves(pos, &moves, cache)
+    let orig_alpha = alpha;
+    let mut value = -INFINITY;
+    let mut best_move = moves[0];
+    for &m in &moves {
+        let outcome = pos.make_move(m);
+        let captures = outcome.coins_captured as isize;
+        let sub_val = if captures > 0 {
+            let (next_val, _) = alpha_beta(pos, alpha - captures, beta - captures, cache, stop);
+            captures + next_val
+        } else {
+            let (next_val, _) = alpha_beta(pos, -beta, -alpha, cache, stop);
+            -next_val
+        };
+        pos.undo_move(m);
+        if stop.load(Ordering::Relaxed) {
+            return (value, None);
+        }
+        if sub_val > value {
+            value = sub_val;
+            best_move = m;
+        }
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    let bound = if value <= orig_alpha {
+        Bound::Upper
+    } else if value >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    };
+    // Store the best move in the canonical orientation, so that it can be
+    // mapped back onto any reflection or rotation of this position
+    let depth = pos.legal_move_count();
+    cache.insert(key, depth, CacheEntry{value: value, bound: bound, best_move: pos.canonical_move(best_move)});
+    (value, Some(best_move))
+}
+
+// Find a move achieving the known value of an endgame. All positions reachable
+// from an endgame are endgames too, so each move can be valued directly.
+// Moves are tried in the same order as in the tree search, so the same move is chosen.
+fn endgame_best_move<M, P>(pos: &mut P, value: isize) -> Option<M>
+where M: Copy, P: EvaluablePosition<M> {
+    let mut moves = moves_to_consider(pos);
+    order_moves(pos, &mut moves);
+    moves.into_iter().find(|&m| {
+        let outcome = pos.make_move(m);
+        let next_val = pos.endgame_value().expect("Endgame should remain an endgame");
+        pos.undo_move(m);
+        let captures = outcome.coins_captured as isize;
+        let sub_val = if captures > 0 { captures + next_val } else { -next_val };
+        sub_val == value
+    })
 }
 
 // Calculate the value function of a given position and a move which achieves that value
 pub fn eval<M, P>(pos: &P) -> (isize, Option<M>)
-where M: Copy, P: EvaluablePosition<M> + Clone {
-    let mut cache = HashMap::new();
+where M: Copy + PartialEq, P: EvaluablePosition<M> + Clone {
+    eval_cached(pos, &mut EvalTable::default())
+}
+
+// As eval, but reusing and adding to the results of earlier searches in a table
+pub fn eval_cached<M, P>(pos: &P, cache: &mut EvalTable<M>) -> (isize, Option<M>)
+where M: Copy + PartialEq, P: EvaluablePosition<M> + Clone {
     let mut pos = pos.clone();
-    eval_cache(&mut pos, &mut cache)
+    if let Some(value) = pos.endgame_value() {
+        return (value, endgame_best_move(&mut pos, value));
+    }
+    mtdf(&mut pos, 0, cache, &AtomicBool::new(false))
+}
+
+// As eval_cached, but giving up once the stop flag is set, for example by another
+// thread when time runs out. The value is only given if the search was completed.
+// Otherwise the move is the best found so far, or failing that the most promising
+// according to the move ordering.
+pub fn eval_until_stopped<M, P>(pos: &P, cache: &mut EvalTable<M>, stop: &AtomicBool) -> (Option<isize>, Option<M>)
+where M: Copy + PartialEq, P: EvaluablePosition<M> + Clone {
+    let mut pos = pos.clone();
+    if let Some(value) = pos.endgame_value() {
+        return (Some(value), endgame_best_move(&mut pos, value));
+    }
+    let (value, best_move) = mtdf(&mut pos, 0, cache, stop);
+    if !stop.load(Ordering::Relaxed) {
+        return (Some(value), best_move);
+    }
+    let best_move = best_move.or_else(|| {
+        let mut moves = moves_to_consider(&mut pos);
+        order_moves(&mut pos, &mut moves);
+        moves.first().copied()
+    });
+    (None, best_move)
+}
+
+// Narrow down the value with a series of null-window searches, each of which
+// only establishes whether the value is above or below a guess (MTD(f)).
+// These cut off far more than a full-window search, and share the cache.
+// The closer the first guess is to the value, the fewer searches are needed.
+// If stopped, the result is a lower bound and a move achieving it, if any.
+fn mtdf<M, P, C>(pos: &mut P, mut guess: isize, cache: &mut C, stop: &AtomicBool) -> (isize, Option<M>)
+where M: Copy + PartialEq, P: EvaluablePosition<M>, C: Cache<CacheEntry<M>> {
+    let (mut lower, mut upper) = (-INFINITY, INFINITY);
+    let mut best_move = None;
+    while lower < upper {
+        let beta = if guess == lower { guess + 1 } else { guess };
+        let (value, m) = alpha_beta(pos, beta - 1, beta, cache, stop);
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        guess = value;
+        if value < beta {
+            upper = value;
+        } else {
+            lower = value;
+            best_move = m;
+        }
+    }
+    (lower, best_move)
+}
+
+// The principal variation: a line of optimal play from the position, of at most
+// max_moves moves, using the results already in the table where possible.
+// Players are numbered relative to the position, 0 being the player to move.
+pub fn principal_variation<M, P>(pos: &P, cache: &mut EvalTable<M>, max_moves: usize) -> Vec<GameMove<M>>
+where M: Copy + PartialEq, P: EvaluablePosition<M> + Clone {
+    let mut pos = pos.clone();
+    let mut player = 0;
+    let mut result = Vec::new();
+    while result.len() < max_moves {
+        let m = match eval_cached(&pos, cache) {
+            (_, Some(m)) => m,
+            (_, None) => break,
+        };
+        let outcome = pos.make_move(m);
+        result.push(GameMove{m: m, player: player, outcome: outcome});
+        if outcome.end_of_turn {
+            player = 1 - player;
+        }
+    }
+    result
+}
+
+// As eval, but searching the moves from the root on several threads at once
+pub fn eval_parallel<M, P>(pos: &P, threads: usize) -> (isize, Option<M>)
+where M: Copy + PartialEq + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    eval_parallel_cached(pos, &mut EvalTable::default(), threads)
+}
+
+// As eval_cached, but splitting the work at the root between several threads,
+// which share the table. This is the same MTD(f) search as in eval_cached, but in each
+// null-window search of the root the first move is searched alone, and only if it
+// fails low are the rest shared out between the threads. As soon as one of them fails
+// high, those after it are abandoned. The result is the same as that of the serial search.
+pub fn eval_parallel_cached<M, P>(pos: &P, cache: &mut EvalTable<M>, threads: usize) -> (isize, Option<M>)
+where M: Copy + PartialEq + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    let mut root = pos.clone();
+    let mut ordered = moves_to_consider(&mut root);
+    if threads <= 1 || ordered.len() <= 1 || root.endgame_value().is_some() {
+        return eval_cached(pos, cache);
+    }
+    order_moves(&mut root, &mut ordered);
+
+    let shared = ShardedTable::new(mem::take(cache), threads * SHARDS_PER_THREAD);
+    let (mut lower, mut upper) = (-INFINITY, INFINITY);
+    let mut guess = 0;
+    let mut best_move = None;
+    // As in alpha_beta, the move which failed high in the last search is tried first
+    let mut cached_move = None;
+    while lower < upper {
+        let beta = if guess == lower { guess + 1 } else { guess };
+        let mut moves = ordered.clone();
+        if let Some(i) = cached_move.and_then(|cm| moves.iter().position(|&m| m == cm)) {
+            moves[..=i].rotate_right(1);
+        }
+        let (value, fail_high) = root_search(pos, &moves, beta, &shared, threads);
+        guess = value;
+        cached_move = fail_high.map(|i| moves[i]);
+        if cached_move.is_some() {
+            lower = value;
+            best_move = cached_move;
+        } else {
+            upper = value;
+        }
+    }
+    *cache = shared.into_table();
+    (lower, best_move)
+}
+
+// Value of a move for the player making it, from a null-window search of the position
+// after it, testing whether the move is worth at least beta. Fail-soft as for alpha_beta.
+fn null_window_value<M, P, C>(pos: &P, m: M, beta: isize, cache: &mut C, stop: &AtomicBool) -> isize
+where M: Copy + PartialEq, P: EvaluablePosition<M> + Clone, C: Cache<CacheEntry<M>> {
+    let mut child = pos.clone();
+    let captures = child.make_move(m).coins_captured as isize;
+    if captures > 0 {
+        captures + alpha_beta(&mut child, beta - 1 - captures, beta - captures, cache, stop).0
+    } else {
+        -alpha_beta(&mut child, -beta, 1 - beta, cache, stop).0
+    }
+}
+
+// Null-window search of the root, testing whether the value is at least beta, giving
+// the fail-soft value and the first move, if any, which achieves at least beta.
+// Each move is searched with the same window as in alpha_beta, so the result is the same.
+fn root_search<M, P>(pos: &P, moves: &[M], beta: isize, table: &ShardedTable<CacheEntry<M>>, threads: usize)
+                     -> (isize, Option<usize>)
+where M: Copy + PartialEq + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    let values: Vec<AtomicIsize> = moves.iter().map(|_| AtomicIsize::new(-INFINITY)).collect();
+    let stops: Vec<AtomicBool> = moves.iter().map(|_| AtomicBool::new(false)).collect();
+    let first_high = AtomicUsize::new(usize::MAX);
+    let search = |i: usize, mut table: &ShardedTable<CacheEntry<M>>| {
+        let value = null_window_value(pos, moves[i], beta, &mut table, &stops[i]);
+        if stops[i].load(Ordering::Relaxed) {
+            return;
+        }
+        values[i].store(value, Ordering::Relaxed);
+        if value >= beta {
+            // Abandon the moves after the first to fail high
+            let first = first_high.fetch_min(i, Ordering::Relaxed).min(i);
+            for stop in &stops[first + 1..] {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    };
+
+    search(0, table);
+    if first_high.load(Ordering::Relaxed) != 0 {
+        let next = AtomicUsize::new(1);
+        thread::scope(|scope| {
+            for _ in 0..threads.min(moves.len() - 1) {
+                scope.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= moves.len() || i > first_high.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        search(i, table);
+                    }
+                });
+            }
+        });
+    }
+    match first_high.into_inner() {
+        usize::MAX => (values.iter().map(|v| v.load(Ordering::Relaxed)).max().unwrap(), None),
+        i => (values[i].load(Ordering::Relaxed), Some(i)),
+    }
+}
+
+// Exact value of a move for the player making it
+fn move_value<M, P, C>(pos: &P, m: M, cache: &mut C, stop: &AtomicBool) -> isize
+where M: Copy + PartialEq, P: EvaluablePosition<M> + Clone, C: Cache<CacheEntry<M>> {
+    let mut child = pos.clone();
+    let captures = child.make_move(m).coins_captured as isize;
+    let (next_val, _) = mtdf(&mut child, 0, cache, stop);
+    if captures > 0 { captures + next_val } else { -next_val }
+}
+
+// Exact values of the given moves for the player making them, searched on up to
+// the given number of threads, which share the table
+fn move_values<M, P>(pos: &P, moves: &[M], cache: &mut EvalTable<M>, threads: usize) -> Vec<isize>
+where M: Copy + PartialEq + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    let never_stop = AtomicBool::new(false);
+    if threads <= 1 {
+        return moves.iter().map(|&m| move_value(pos, m, cache, &never_stop)).collect();
+    }
+
+    let shared = ShardedTable::new(mem::take(cache), threads * SHARDS_PER_THREAD);
+    let next = AtomicUsize::new(0);
+    let values = Mutex::new(vec!(-INFINITY; moves.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads.min(moves.len()) {
+            scope.spawn(|| {
+                let mut table = &shared;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= moves.len() {
+                        break;
+                    }
+                    let sub_val = move_value(pos, moves[i], &mut table, &never_stop);
+                    values.lock().unwrap()[i] = sub_val;
+                }
+            });
+        }
+    });
+    *cache = shared.into_table();
+    values.into_inner().unwrap()
+}
+
+// Calculate the value of a position, along with the value of each legal move
+// for the player making it. Of a set of equivalent moves, only one is included.
+pub fn eval_with_moves<M, P>(pos: &P) -> (isize, HashMap<M, isize>)
+where M: Copy + Eq + Hash + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    eval_with_moves_cached(pos, &mut EvalTable::default())
+}
+
+// As eval_with_moves, but reusing and adding to the results of earlier searches in a table
+pub fn eval_with_moves_cached<M, P>(pos: &P, cache: &mut EvalTable<M>) -> (isize, HashMap<M, isize>)
+where M: Copy + Eq + Hash + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    eval_with_moves_parallel_cached(pos, cache, 1)
+}
+
+// As eval_with_moves_cached, but searching the moves on several threads at once
+pub fn eval_with_moves_parallel_cached<M, P>(pos: &P, cache: &mut EvalTable<M>, threads: usize)
+                                             -> (isize, HashMap<M, isize>)
+where M: Copy + Eq + Hash + Send + Sync, P: EvaluablePosition<M> + Clone + Sync {
+    let mut moves: Vec<M> = Vec::new();
+    for m in pos.legal_moves() {
+        if !moves.iter().any(|&n| pos.moves_equivalent(m, n)) {
+            moves.push(m);
+        }
+    }
+    let values = move_values(pos, &moves, cache, threads);
+    let value = values.iter().copied().max().unwrap_or(0);
+    (value, moves.into_iter().zip(values).collect())
 }
 
 #[cfg(test)]
 mod test {
     use crate::eval::*;
     use crate::examples::*;
+    use crate::game::Symmetry;
+    use crate::ttable::Replacement;
 
     use rand::{Rng, SeedableRng};
     use rand::rngs::StdRng;
@@ -399,7 +821,210 @@ mod test {
         }
     }
 
-    // TODO: Test that rotations and reflections do not affect the evaluation
+    #[test]
+    fn windowed_search_bounds() {
+        let mut seed: [u8; 32] = [0; 32];
+        seed[0] = 45;
+        let mut r: StdRng = SeedableRng::from_seed(seed);
+        for _ in 0..200 {
+            let mut pos = make_random_pos(&mut r);
+            let expected_val = naive_minimax(&mut pos);
+            let alpha: isize = r.gen_range(-6..6);
+            let beta = alpha + r.gen_range(1..4);
+            let (val, _) = alpha_beta(&mut pos, alpha, beta, &mut EvalTable::default(), &AtomicBool::new(false));
+            // Fail-soft: the true value when inside the window, otherwise a bound on the far side of it
+            if expected_val <= alpha {
+                assert!(expected_val <= val && val <= alpha, "({}, {}) gave {} for {}\n{}", alpha, beta, val, expected_val, pos);
+            } else if expected_val >= beta {
+                assert!(beta <= val && val <= expected_val, "({}, {}) gave {} for {}\n{}", alpha, beta, val, expected_val, pos);
+            } else {
+                assert_eq!(expected_val, val, "({}, {})\n{}", alpha, beta, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn reused_tables() {
+        let mut pos = ex3p12();
+        let mut table = EvalTable::default();
+        let (val, best_move) = eval_cached(&pos, &mut table);
+        let misses = table.stats().misses;
+        assert_eq!(eval(&pos), (val, best_move));
+        // Searching again after a move finds much of the work already done
+        let captures = pos.make_move(best_move.unwrap()).coins_captured as isize;
+        let (next_val, _) = eval_cached(&pos, &mut table);
+        assert_eq!(val, if captures > 0 { captures + next_val } else { -next_val });
+        assert!(table.stats().hits > 0);
+        assert!(table.stats().misses - misses < misses);
+
+        // A table too small to hold the whole search still gives the right values
+        let mut small_table = EvalTable::new(1 << 10, Replacement::AlwaysReplace);
+        assert_eq!(next_val, eval_cached(&pos, &mut small_table).0);
+        assert!(small_table.stats().replacements > 0);
+    }
+
+    #[test]
+    fn parallel_search() {
+        let mut seed: [u8; 32] = [0; 32];
+        seed[0] = 67;
+        let mut r: StdRng = SeedableRng::from_seed(seed);
+        for _ in 0..50 {
+            let pos = make_random_pos(&mut r);
+            assert_eq!(eval(&pos), eval_parallel(&pos, 3), "{}", pos);
+        }
+        let pos = ex3p12();
+        assert_eq!(eval(&pos), eval_parallel(&pos, 4));
+        for i in 0..4 {
+            let pos = one_long_multi_three(i, 4);
+            assert_eq!(eval(&pos).0, eval_parallel(&pos, 2).0);
+        }
+
+        // The shared table is kept for later searches
+        let mut pos = ex3p1();
+        let mut table = EvalTable::default();
+        let (val, best_move) = eval_parallel_cached(&pos, &mut table, 2);
+        assert_eq!((3, true), (val, pos.moves_equivalent(best_move.unwrap(), Move{x: 2, y: 1, side: Side::Bottom})));
+        assert!(table.len() > 0);
+        pos.make_move(best_move.unwrap());
+        assert_eq!(-3, eval_parallel_cached(&pos, &mut table, 2).0);
+        assert!(table.stats().hits > 0);
+    }
+
+    #[test]
+    fn parallel_search_work() {
+        // Searching the root moves in parallel against a shared bound does no more
+        // work than the serial search, as measured by lookups in the table.
+        // In this position whenever a move fails high at the root it is the first
+        // one tried, so no thread searches a move the serial search would not.
+        let mut pos = SimplePosition::new_game(4, 3);
+        for (x, y, side) in [(1, 0, Side::Right), (1, 1, Side::Bottom), (0, 0, Side::Bottom), (0, 1, Side::Right),
+                             (3, 2, Side::Bottom), (0, 2, Side::Left), (0, 2, Side::Right), (3, 1, Side::Bottom),
+                             (2, 1, Side::Right), (3, 0, Side::Top)] {
+            pos.make_move(Move::new(x, y, side));
+        }
+        let probes = |table: &EvalTable<Move>| table.stats().hits + table.stats().misses;
+        let mut serial = EvalTable::default();
+        let serial_result = eval_cached(&pos, &mut serial);
+        for threads in [2, 4, 8] {
+            let mut parallel = EvalTable::default();
+            assert_eq!(serial_result, eval_parallel_cached(&pos, &mut parallel, threads));
+            assert!(probes(&parallel) <= probes(&serial),
+                    "{} threads: {} probes, serial {}", threads, probes(&parallel), probes(&serial));
+        }
+    }
+
+    #[test]
+    fn stopped_search() {
+        let pos = ex3p12();
+        let mut table = EvalTable::default();
+        let (val, best_move) = eval_until_stopped(&pos, &mut table, &AtomicBool::new(true));
+        assert_eq!(None, val);
+        assert!(pos.is_legal_move(best_move.unwrap()));
+        // Nothing half-finished is left in the table to spoil later searches
+        assert_eq!(true, table.is_empty());
+        assert_eq!(eval(&pos), eval_cached(&pos, &mut table));
+        let (val, _) = eval_until_stopped(&pos, &mut table, &AtomicBool::new(false));
+        assert_eq!(Some(eval(&pos).0), val);
+    }
+
+    #[test]
+    fn principal_variations() {
+        for pos in vec!(ex3p1(), ex3p12(), double_chain(4), double_loop(3)) {
+            let mut table = EvalTable::default();
+            let (val, best_move) = eval_cached(&pos, &mut table);
+            let pv = principal_variation(&pos, &mut table, usize::MAX);
+            assert_eq!(best_move, pv.first().map(|gm| gm.m));
+            // Following the line to the end of the game achieves the value
+            let mut scores = [0, 0];
+            for gm in pv.iter() {
+                scores[gm.player] += gm.outcome.coins_captured as isize;
+            }
+            assert_eq!(val, scores[0] - scores[1], "{}", pos);
+            assert_eq!(true, pv.last().unwrap().outcome.end_of_game);
+
+            let short_pv = principal_variation(&pos, &mut table, 3);
+            assert_eq!(3, short_pv.len());
+            assert_eq!(true, short_pv.iter().zip(pv.iter()).all(|(a, b)| a.m == b.m));
+        }
+        assert_eq!(0, principal_variation(&SimplePosition::new_end_game(2, 2), &mut EvalTable::default(), 5).len());
+    }
+
+    #[test]
+    fn per_move_values() {
+        let mut pos = ex3p1();
+        let (val, per_move) = eval_with_moves(&pos);
+        assert_eq!(3, val);
+        assert_eq!(pos.legal_moves().len(), per_move.len());
+        let (_, best_move) = eval(&pos);
+        assert_eq!(Some(&val), per_move.get(&best_move.unwrap()));
+        for (&m, &m_val) in per_move.iter() {
+            let captures = pos.make_move(m).coins_captured as isize;
+            let (next_val, _) = eval(&pos);
+            pos.undo_move(m);
+            assert_eq!(if captures > 0 { captures + next_val } else { -next_val }, m_val, "{}", m);
+        }
+
+        // Equivalent moves are only included once
+        let pos = CompoundPosition::new(vec!(double_loop(2), make_chain(3)));
+        let mut table = EvalTable::default();
+        let (val, per_move) = eval_with_moves_parallel_cached(&pos, &mut table, 3);
+        assert_eq!(eval(&pos).0, val);
+        assert_eq!(pos.legal_moves().len(), per_move.len());
+        assert_eq!((val, per_move), eval_with_moves_cached(&pos, &mut table));
+
+        let (val, per_move) = eval_with_moves(&SimplePosition::new_end_game(2, 2));
+        assert_eq!((0, true), (val, per_move.is_empty()));
+    }
+
+    #[test]
+    fn move_ordering() {
+        let mut pos = SimplePosition::new_game(3, 1);
+        pos.make_move(Move{x: 0, y: 0, side: Side::Top});
+        pos.make_move(Move{x: 0, y: 0, side: Side::Bottom});
+        pos.make_move(Move{x: 0, y: 0, side: Side::Left});
+        pos.make_move(Move{x: 2, y: 0, side: Side::Top});
+        pos.make_move(Move{x: 2, y: 0, side: Side::Bottom});
+        // Coin (0,0) is capturable; the right coin can be sacrificed; the middle is safe
+        let mut moves = pos.legal_moves();
+        order_moves(&mut pos, &mut moves);
+        assert_eq!(Move{x: 0, y: 0, side: Side::Right}, moves[0]);
+        assert_eq!(false, pos.is_sacrifice(moves[1]));
+        assert_eq!(true, pos.is_sacrifice(moves[moves.len() - 1]));
+    }
+
+    #[test]
+    fn ddeal_errors() {
+        let mut pos = make_chain(3);
+        let capture = Move::new(0, 0, Side::Right);
+        assert_eq!(Err(Error::NotLoony(capture.to_string())), pos.try_find_ddeal_move(capture));
+        pos.make_move(Move::new(0, 0, Side::Left));
+        assert_eq!(Ok(Move::new(1, 0, Side::Right)), pos.try_find_ddeal_move(capture));
+        let not_capture = Move::new(2, 0, Side::Right);
+        assert_eq!(Err(Error::NotLoony(not_capture.to_string())), pos.try_find_ddeal_move(not_capture));
+
+        let pos = CompoundPosition::from_single(pos);
+        let capture = CPosMove::new(1, 0, 0, Side::Right);
+        assert_eq!(Err(Error::NotLoony(capture.to_string())), pos.try_find_ddeal_move(capture));
+    }
+
+    #[test]
+    fn symmetric_evaluations() {
+        for pos in [ex3p1(), p50_bottomleft(), p50_top(), icelandic_game(2, 3)] {
+            let (val, _) = eval(&pos);
+            for sym in Symmetry::all(pos.width(), pos.height()) {
+                let image = pos.transform(sym);
+                let (image_val, best_move) = eval(&image);
+                assert_eq!(val, image_val, "{:?}\n{}", sym, image);
+                // The best move may come from the cache entry for another orientation,
+                // so must have been mapped back correctly
+                let mut image = image;
+                let outcome = image.make_move(best_move.unwrap());
+                let (next_val, _) = eval(&image);
+                let sign = if outcome.coins_captured > 0 { 1 } else { -1 };
+                assert_eq!(val, outcome.coins_captured as isize + sign * next_val);
+            }
+        }
+    }
 
 //    #[test]
 //    fn eval_p50() {
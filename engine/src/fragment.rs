@@ -0,0 +1,348 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::error::Error;
+use crate::game::{Move, Position, SimplePosition, Side};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::str::FromStr;
+
+// Canonical form of a fragment of a dots-and-boxes position, i.e. of the strings-and-coins
+// graph it makes. This is the same wherever the fragment appears on the board and however
+// it is reflected or rotated. All the ground links are merged into one ground node, so only
+// the number of ground links of each coin is kept and not the sides they are on.
+// Positions with the same canonical form have the same Nimstring value.
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+#[derive(PartialOrd)]
+#[derive(Ord)]
+#[derive(Debug)]
+pub struct CanonicalFragment {
+    width: usize,
+    height: usize,
+    // For each coin, row by row: its number of ground links, plus flags for
+    // strings to the coins to its right and below
+    coins: Vec<u8>,
+}
+
+const RIGHT_LINK: u8 = 1 << 3;
+const DOWN_LINK: u8 = 1 << 4;
+const GROUND_MASK: u8 = RIGHT_LINK - 1;
+
+// Digits for coins in the text form
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuv";
+
+impl CanonicalFragment {
+    pub fn new(pos: &SimplePosition) -> CanonicalFragment {
+        // Crop to the coins still in play
+        let (mut x_left, mut x_right, mut y_top, mut y_bottom) = (usize::MAX, 0, usize::MAX, 0);
+        for x in 0..pos.width() {
+            for y in 0..pos.height() {
+                if pos.valency(x, y) > 0 {
+                    (x_left, x_right) = (x_left.min(x), x_right.max(x));
+                    (y_top, y_bottom) = (y_top.min(y), y_bottom.max(y));
+                }
+            }
+        }
+        if x_left == usize::MAX {
+            return CanonicalFragment{width: 0, height: 0, coins: Vec::new()};
+        }
+        let (width, height) = (x_right - x_left + 1, y_bottom - y_top + 1);
+
+        // The coins in the original orientation
+        let mut coins = vec!(0; width * height);
+        for x in x_left..=x_right {
+            for y in y_top..=y_bottom {
+                let mut coin = 0;
+                for side in Side::ALL {
+                    if !pos.is_legal_move(Move{x: x, y: y, side: side}) {
+                        continue;
+                    }
+                    match (pos.offset(x, y, side), side) {
+                        (None, _) => coin += 1,
+                        (Some(_), Side::Right) => coin |= RIGHT_LINK,
+                        (Some(_), Side::Bottom) => coin |= DOWN_LINK,
+                        _ => (),
+                    }
+                }
+                coins[(y - y_top) * width + x - x_left] = coin;
+            }
+        }
+        CanonicalFragment{width: width, height: height, coins: coins}.canonical()
+    }
+
+    // Take the least of the forms of a fragment under each symmetry
+    fn canonical(self: CanonicalFragment) -> CanonicalFragment {
+        let mut result = self.clone();
+        let mut candidate = Vec::with_capacity(self.coins.len());
+        for transpose in [false, true] {
+            for flip_y in [false, true] {
+                for flip_x in [false, true] {
+                    let size = self.transform(flip_x, flip_y, transpose, &mut candidate);
+                    if (size, &candidate) < ((result.width, result.height), &result.coins) {
+                        (result.width, result.height) = size;
+                        mem::swap(&mut result.coins, &mut candidate);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // A position of the same size as the fragment which has the fragment's graph,
+    // with the ground links of each coin on its sides at the edge of the board
+    pub fn to_position(self: &CanonicalFragment) -> SimplePosition {
+        let mut pos = SimplePosition::new_end_game(self.width.max(1), self.height.max(1));
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coin = self.coin(x, y);
+                if coin & RIGHT_LINK != 0 {
+                    pos.undo_move(Move{x: x, y: y, side: Side::Right});
+                }
+                if coin & DOWN_LINK != 0 {
+                    pos.undo_move(Move{x: x, y: y, side: Side::Bottom});
+                }
+                let edges: Vec<Side> = Side::ALL.into_iter().filter(|&side| pos.offset(x, y, side).is_none()).collect();
+                for &side in edges.iter().take((coin & GROUND_MASK) as usize) {
+                    pos.undo_move(Move{x: x, y: y, side: side});
+                }
+            }
+        }
+        pos
+    }
+
+    // Key for the canonical form in a transposition table
+    pub fn key(self: &CanonicalFragment) -> usize {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish() as usize
+    }
+
+    // Number of coins in the fragment
+    pub fn coin_count(self: &CanonicalFragment) -> usize {
+        (0..self.width).flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .filter(|&(x, y)| self.valency(x, y) > 0)
+            .count()
+    }
+
+    fn valency(self: &CanonicalFragment, x: usize, y: usize) -> usize {
+        let coin = self.coin(x, y);
+        let mut result = (coin & GROUND_MASK) as usize;
+        result += (coin & RIGHT_LINK != 0) as usize + (coin & DOWN_LINK != 0) as usize;
+        result += (x > 0 && self.coin(x - 1, y) & RIGHT_LINK != 0) as usize;
+        result += (y > 0 && self.coin(x, y - 1) & DOWN_LINK != 0) as usize;
+        result
+    }
+
+    fn coin(self: &CanonicalFragment, x: usize, y: usize) -> u8 {
+        self.coins[y * self.width + x]
+    }
+
+    // Indicate whether there is a string between two adjacent coins
+    fn linked(self: &CanonicalFragment, (x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> bool {
+        if x1 == x2 {
+            self.coin(x1, y1.min(y2)) & DOWN_LINK != 0
+        } else {
+            self.coin(x1.min(x2), y1) & RIGHT_LINK != 0
+        }
+    }
+
+    // Reflect in either axis, then optionally in the diagonal, putting the coins
+    // of the result in the given vector and returning its size
+    fn transform(self: &CanonicalFragment, flip_x: bool, flip_y: bool, transpose: bool,
+                 coins: &mut Vec<u8>) -> (usize, usize) {
+        let (width, height) = if transpose { (self.height, self.width) } else { (self.width, self.height) };
+        let source = |x: usize, y: usize| {
+            let (x, y) = if transpose { (y, x) } else { (x, y) };
+            (if flip_x { self.width - 1 - x } else { x }, if flip_y { self.height - 1 - y } else { y })
+        };
+        coins.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = source(x, y);
+                let mut coin = self.coin(sx, sy) & GROUND_MASK;
+                if x + 1 < width && self.linked((sx, sy), source(x + 1, y)) {
+                    coin |= RIGHT_LINK;
+                }
+                if y + 1 < height && self.linked((sx, sy), source(x, y + 1)) {
+                    coin |= DOWN_LINK;
+                }
+                coins.push(coin);
+            }
+        }
+        (width, height)
+    }
+}
+
+// Text form: the size, then a digit for each coin, with rows separated by slashes
+impl fmt::Display for CanonicalFragment {
+    fn fmt(self: &CanonicalFragment, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}:", self.width, self.height)?;
+        for (y, row) in self.coins.chunks(self.width.max(1)).enumerate() {
+            if y > 0 {
+                write!(f, "/")?;
+            }
+            for &coin in row {
+                write!(f, "{}", DIGITS[coin as usize] as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Read the text form of a fragment, which must be in canonical form and describe
+// a fragment which could appear on a board
+impl FromStr for CanonicalFragment {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<CanonicalFragment, Error> {
+        let invalid = |message: &str| Error::InvalidGraph(format!("Invalid fragment [{}]: {}", text, message));
+        let (size, rows) = text.split_once(':').ok_or_else(|| invalid("no size"))?;
+        let (width, height) = size.split_once('x').and_then(|(w, h)| w.parse::<usize>().ok().zip(h.parse::<usize>().ok()))
+            .ok_or_else(|| invalid("size must be of the form WxH"))?;
+        if (width == 0) != (height == 0) {
+            return Err(invalid("a fragment with no coins must have size 0x0"));
+        }
+        let rows: Vec<&str> = if height == 0 { Vec::new() } else { rows.split('/').collect() };
+        if rows.len() != height || (height == 0 && !text.ends_with(':')) {
+            return Err(invalid("wrong number of rows"));
+        }
+        let mut coins = Vec::with_capacity(width * height);
+        for row in rows {
+            if row.len() != width {
+                return Err(invalid("wrong number of coins in a row"));
+            }
+            for c in row.bytes() {
+                coins.push(DIGITS.iter().position(|&d| d == c).ok_or_else(|| invalid("invalid coin"))? as u8);
+            }
+        }
+        let result = CanonicalFragment{width: width, height: height, coins: coins};
+        for y in 0..height {
+            for x in 0..width {
+                let coin = result.coin(x, y);
+                let edge_sides = (x == 0) as u8 + (x + 1 == width) as u8 + (y == 0) as u8 + (y + 1 == height) as u8;
+                if (coin & RIGHT_LINK != 0 && x + 1 == width) || (coin & DOWN_LINK != 0 && y + 1 == height) {
+                    return Err(invalid("string off the edge of the fragment"));
+                }
+                if coin & GROUND_MASK > edge_sides || result.valency(x, y) > 4 {
+                    return Err(invalid("too many strings on a coin"));
+                }
+            }
+        }
+        let live = |x: usize, y: usize| result.valency(x, y) > 0;
+        if width > 0 && !((0..width).any(|x| live(x, 0)) && (0..width).any(|x| live(x, height - 1))
+                          && (0..height).any(|y| live(0, y)) && (0..height).any(|y| live(width - 1, y))) {
+            return Err(invalid("not cropped to its coins"));
+        }
+        if result != result.clone().canonical() {
+            return Err(invalid("not in canonical form"));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fragment::*;
+    use crate::examples::*;
+    use crate::splitter::SplittablePosition;
+
+    // Rotate a position a quarter turn clockwise
+    fn rotate(pos: &SimplePosition) -> SimplePosition {
+        let mut result = SimplePosition::new_end_game(pos.height(), pos.width());
+        for m in pos.legal_moves() {
+            let side = match m.side {
+                Side::Top => Side::Right,
+                Side::Right => Side::Bottom,
+                Side::Bottom => Side::Left,
+                Side::Left => Side::Top,
+            };
+            let rotated = Move{x: pos.height() - 1 - m.y, y: m.x, side: side};
+            if !result.is_legal_move(rotated) {
+                result.undo_move(rotated);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn text_form() {
+        assert_eq!("1x3:h/g/1", format!("{}", CanonicalFragment::new(&make_chain(3))));
+        assert_eq!("1x1:4", format!("{}", CanonicalFragment::new(&SimplePosition::new_game(1, 1))));
+        assert_eq!("0x0:", format!("{}", CanonicalFragment::new(&SimplePosition::new_end_game(2, 2))));
+        assert_eq!(3, CanonicalFragment::new(&make_chain(3)).coin_count());
+        assert_eq!(6, CanonicalFragment::new(&p50_bottomleft()).coin_count());
+    }
+
+    #[test]
+    fn text_parsing() {
+        for pos in vec!(p50_top(), p50_bottomleft(), p50_bottomright(), ex3p1(), make_chain(3), SimplePosition::new_end_game(2, 2)) {
+            let fragment = CanonicalFragment::new(&pos);
+            assert_eq!(Ok(fragment.clone()), fragment.to_string().parse::<CanonicalFragment>());
+            assert_eq!(fragment, CanonicalFragment::new(&fragment.to_position()));
+        }
+        // Each of these is malformed, could not be on a board, or is not in canonical form
+        for text in vec!("1x3", "1x3:h/g", "1x3:h/g/12", "1x3:h/g/z", "1x3:h/g/i",
+                         "1x3:4/g/1", "1x1:5", "1x2:0/4", "0x1:", "1x3:1/g/h", "3x1:9a1", "2x1:91") {
+            assert_eq!(true, text.parse::<CanonicalFragment>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn same_everywhere() {
+        for pos in vec!(p50_top(), p50_bottomleft(), p50_bottomright(), ex3p1(), ex6p2(), make_chain(4)) {
+            let fragment = CanonicalFragment::new(&pos);
+            let mut rotated = pos.clone();
+            for _ in 0..3 {
+                rotated = rotate(&rotated);
+                assert_eq!(fragment, CanonicalFragment::new(&rotated));
+            }
+        }
+        // The parts of p50 are found in place and in fragments of their own
+        let pos = p50();
+        let mut fragments: Vec<CanonicalFragment> = pos.split().iter().map(CanonicalFragment::new).collect();
+        fragments.sort();
+        let mut expected: Vec<CanonicalFragment> = vec!(p50_top(), p50_bottomleft(), p50_bottomright())
+            .iter().map(CanonicalFragment::new).collect();
+        expected.sort();
+        assert_eq!(expected, fragments);
+        assert_eq!(false, CanonicalFragment::new(&p50_top()) == CanonicalFragment::new(&p50_bottomleft()));
+    }
+
+    #[test]
+    fn ground_links_merged() {
+        // A coin with ground links on opposite sides is the same as one with ground links
+        // on adjacent sides, although the two are not reflections of one another
+        let mut opposite = SimplePosition::new_game(1, 1);
+        opposite.make_move(Move{x: 0, y: 0, side: Side::Left});
+        opposite.make_move(Move{x: 0, y: 0, side: Side::Right});
+        let mut adjacent = SimplePosition::new_game(1, 1);
+        adjacent.make_move(Move{x: 0, y: 0, side: Side::Right});
+        adjacent.make_move(Move{x: 0, y: 0, side: Side::Bottom});
+        assert_eq!(CanonicalFragment::new(&opposite), CanonicalFragment::new(&adjacent));
+        assert_eq!(CanonicalFragment::new(&opposite).key(), CanonicalFragment::new(&adjacent).key());
+        assert_eq!(CanonicalFragment::new(&opposite), CanonicalFragment::new(&make_chain(1)));
+        let mut three_links = SimplePosition::new_game(1, 1);
+        three_links.make_move(Move{x: 0, y: 0, side: Side::Top});
+        assert_eq!(false, CanonicalFragment::new(&opposite) == CanonicalFragment::new(&three_links));
+    }
+}
@@ -16,10 +16,16 @@
     You should have received a copy of the GNU Affero General Public License
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
+use crate::error::Error;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
-use std::iter;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::Arc;
 
 #[derive(Clone)]
 #[derive(Copy)]
@@ -29,13 +35,17 @@ use std::iter;
 #[derive(PartialOrd)]
 #[derive(Ord)]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub enum Side {
     Top, Bottom, Left, Right
 }
 
 impl Side {
+    pub const ALL: [Side; 4] = [Side::Top, Side::Bottom, Side::Left, Side::Right];
+
     pub fn all() -> Vec<Side> {
-        vec!(Side::Top, Side::Bottom, Side::Left, Side::Right)
+        Side::ALL.to_vec()
     }
 
     pub fn all_except(side: Side) -> Vec<Side> {
@@ -72,6 +82,10 @@ impl fmt::Display for Side {
 #[derive(Clone)]
 #[derive(Copy)]
 #[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct MoveOutcome {
     pub coins_captured: usize,
     pub end_of_turn: bool,
@@ -84,6 +98,8 @@ pub struct MoveOutcome {
 #[derive(Hash)]
 #[derive(PartialEq)]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Move {
     pub x: usize,
     pub y: usize,
@@ -108,14 +124,30 @@ pub trait Position<M> {
     // Indicate how many coins a given move would capture (either 0, 1 or 2)
     fn would_capture(&self, m: M) -> usize;
     // Make a given move on the board, and indicate the outcome.
+    // Panics if the move is not legal: use try_make_move for unvalidated input.
     fn make_move(&mut self, m: M) -> MoveOutcome;
     // Undo a given move by putting the line back on the board.
     // Behaviour if the move was never made in the first place is undefined.
     fn undo_move(&mut self, m: M);
+    // Make a given move on the board, or report why it cannot be made.
+    fn try_make_move(&mut self, m: M) -> Result<MoveOutcome, Error>
+    where M: Copy + fmt::Display {
+        if self.is_legal_move(m) {
+            Ok(self.make_move(m))
+        } else {
+            Err(Error::IllegalMove(m.to_string()))
+        }
+    }
+    // Undo a given move, or report an error if its string is not missing from the board.
+    fn try_undo_move(&mut self, m: M) -> Result<(), Error>;
     // Indicate whether the game is over (i.e. whether all strings have been cut).
     fn is_end_of_game(&self) -> bool;
     // Compute all possible legal moves in the position.
     fn legal_moves(&self) -> Vec<M>;
+    // Number of legal moves, i.e. strings left on the board
+    fn legal_move_count(&self) -> usize {
+        self.legal_moves().len()
+    }
     // Indicate whether two moves are equivalent in the current position,
     // i.e. they refer to the same "logical" move on the board.
     // For example in a SimplePosition, (0,0) Right is equivalent to (1,0) Left
@@ -125,69 +157,200 @@ pub trait Position<M> {
     // This hash should be consistent across positions,
     // i.e. equal positions should have equal hashes.
     fn zhash(&self) -> usize;
+    // Hash value which is also equal for positions which are reflections or rotations
+    // of one another. It is the zhash of one of these images, the canonical orientation.
+    // Position types with no notion of symmetry can rely on the default of zhash.
+    fn canonical_zhash(&self) -> usize {
+        self.zhash()
+    }
+    // Map a move in this position to the corresponding move in the canonical orientation.
+    fn canonical_move(&self, m: M) -> M {
+        m
+    }
+    // Map a move in the canonical orientation back to the corresponding move in this position.
+    fn move_from_canonical(&self, m: M) -> M {
+        m
+    }
 }
 
-// An m*n dots-and-boxes position is represented as:
-// * a row of top ground links
-// * a column of left ground links
-// * an m*n array of downward-pointing links
-// * an m*n array of rightward-pointing links
+// An m*n dots-and-boxes position is represented as a pair of bitboards,
+// one bit per string and one machine word per row of the board:
+// * horizontal_strings[y] has bit x set if the string along the top of square (x,y)
+//   is still present (with y ranging over 0..=n, so row n is the bottom edge)
+// * vertical_strings[y] has bit x set if the string along the left of square (x,y)
+//   is still present (with x ranging over 0..=m, so column m is the right edge)
+// This limits the board to at most MAX_WIDTH columns, but allows the valency of a
+// coin, or the set of captured coins in a row, to be found with a few bit operations.
 // Position coordinates originate at the top left and are 0-based,
 // so x=1,y=2 is the second square in the third row.
+// When serialised, a position is described by its size and the lines drawn on it.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "SimplePositionRecord", try_from = "SimplePositionRecord"))]
 pub struct SimplePosition {
-    top_strings: Vec<bool>,
-    left_strings: Vec<bool>,
-    down_strings: Vec<Vec<bool>>,
-    right_strings: Vec<Vec<bool>>,
+    width: usize,
+    horizontal_strings: Vec<u64>,
+    vertical_strings: Vec<u64>,
     zhash: ZHash,
 }
 
+// Widest board which can be represented (the vertical strings of a row need width+1 bits)
+pub const MAX_WIDTH: usize = 63;
+
+// Location of a string on the board, in the coordinates of the bitboards
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+struct StringRef {
+    horizontal: bool,
+    x: usize,
+    y: usize,
+}
+
+impl StringRef {
+    fn from_move(m: Move) -> StringRef {
+        match m.side {
+            Side::Top => StringRef{horizontal: true, x: m.x, y: m.y},
+            Side::Bottom => StringRef{horizontal: true, x: m.x, y: m.y + 1},
+            Side::Left => StringRef{horizontal: false, x: m.x, y: m.y},
+            Side::Right => StringRef{horizontal: false, x: m.x + 1, y: m.y},
+        }
+    }
+
+    // One of the moves which cuts this string
+    fn to_move(self: &StringRef, width: usize, height: usize) -> Move {
+        match (self.horizontal, self.x, self.y) {
+            (true, x, y) if y == height => Move::new(x, y - 1, Side::Bottom),
+            (true, x, y) => Move::new(x, y, Side::Top),
+            (false, x, y) if x == width => Move::new(x - 1, y, Side::Right),
+            (false, x, y) => Move::new(x, y, Side::Left),
+        }
+    }
+
+    // Number of strings on a board of a given size
+    fn count(width: usize, height: usize) -> usize {
+        width * (height + 1) + (width + 1) * height
+    }
+
+    // Sequential numbering of the strings on a board, horizontal strings first
+    fn index(self: &StringRef, width: usize, height: usize) -> usize {
+        if self.horizontal {
+            self.y * width + self.x
+        } else {
+            width * (height + 1) + self.y * (width + 1) + self.x
+        }
+    }
+
+    fn from_index(idx: usize, width: usize, height: usize) -> StringRef {
+        let horizontal_count = width * (height + 1);
+        if idx < horizontal_count {
+            StringRef{horizontal: true, x: idx % width, y: idx / width}
+        } else {
+            let idx = idx - horizontal_count;
+            StringRef{horizontal: false, x: idx % (width + 1), y: idx / (width + 1)}
+        }
+    }
+}
+
 impl SimplePosition {
     // Create a new dots-and-boxes position of a given size.
+    // Panics if the board is too wide to represent.
     pub fn new_game(width: usize, height: usize) -> SimplePosition {
-        SimplePosition::make_position(width, height, true)
+        SimplePosition::try_new_game(width, height).unwrap_or_else(|e| panic!("{}", e))
     }
 
     // Create a new dots-and-boxes position of a given size but with all moves completed.
     pub fn new_end_game(width: usize, height: usize) -> SimplePosition {
-        SimplePosition::make_position(width, height, false)
+        SimplePosition::make_position(width, height, false).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Create a new dots-and-boxes position, or report why the size is not supported.
+    pub fn try_new_game(width: usize, height: usize) -> Result<SimplePosition, Error> {
+        SimplePosition::make_position(width, height, true)
     }
 
-    fn make_position(width: usize, height: usize, init_string: bool) -> SimplePosition {
-        let top_strings = iter::repeat(init_string).take(width).collect();
-        let left_strings = iter::repeat(init_string).take(height).collect();
-        let mut right_strings = Vec::with_capacity(width);
-        let mut down_strings = Vec::with_capacity(width);
-        for _ in 0..width {
-            right_strings.push(iter::repeat(init_string).take(height).collect());
-            down_strings.push(iter::repeat(init_string).take(height).collect());
+    fn make_position(width: usize, height: usize, init_string: bool) -> Result<SimplePosition, Error> {
+        if width > MAX_WIDTH {
+            return Err(Error::BoardTooWide(width));
         }
-        SimplePosition {
-            top_strings: top_strings,
-            left_strings: left_strings,
-            down_strings: down_strings,
-            right_strings: right_strings,
-            zhash: ZHash::new(width, height),
+        let (h_row, v_row) = if init_string {
+            (low_bits(width), low_bits(width + 1))
+        } else {
+            (0, 0)
+        };
+        let mut zhash = ZHash::new(width, height);
+        if !init_string {
+            zhash.toggle_all();
         }
+        Ok(SimplePosition {
+            width,
+            horizontal_strings: vec![h_row; height + 1],
+            vertical_strings: vec![v_row; height],
+            zhash,
+        })
     }
 
     pub fn width(self: &SimplePosition) -> usize {
-        self.top_strings.len()
+        self.width
     }
 
     pub fn height(self: &SimplePosition) -> usize {
-        self.left_strings.len()
+        self.vertical_strings.len()
     }
 
     // Indicate whether a given square has been captured.
     pub fn is_captured(self: &SimplePosition, x: usize, y: usize) -> bool {
-        Side::all().iter().all(|&s| !self.is_legal_move(Move{x: x, y: y, side: s}))
+        (self.uncaptured_mask(y) >> x) & 1 == 0
     }
 
     // Valency or degree of coin at a given position
     pub fn valency(self: &SimplePosition, x: usize, y: usize) -> usize {
-        Side::all().iter().filter(|&&s| self.is_legal_move(Move{x: x, y: y, side: s})).count()
+        let top = (self.horizontal_strings[y] >> x) & 1;
+        let bottom = (self.horizontal_strings[y + 1] >> x) & 1;
+        let sides = (self.vertical_strings[y] >> x) & 3;
+        (top + bottom) as usize + sides.count_ones() as usize
+    }
+
+    // Bitmask of the squares in a given row which still have at least one string attached
+    fn uncaptured_mask(self: &SimplePosition, y: usize) -> u64 {
+        let v = self.vertical_strings[y];
+        self.horizontal_strings[y] | self.horizontal_strings[y + 1] | v | (v >> 1)
+    }
+
+    // All coins of valency 1, i.e. those which could be captured by the next move,
+    // found a row at a time by bit-sliced addition of the four strings around each coin
+    pub fn capturable_coins(self: &SimplePosition) -> Vec<(usize, usize)> {
+        let row_mask = low_bits(self.width());
+        let mut result = Vec::new();
+        for y in 0..self.height() {
+            let (top, bottom) = (self.horizontal_strings[y], self.horizontal_strings[y + 1]);
+            let (left, right) = (self.vertical_strings[y], self.vertical_strings[y] >> 1);
+            let (tb_sum, tb_carry) = (top ^ bottom, top & bottom);
+            let (lr_sum, lr_carry) = (left ^ right, left & right);
+            let odd = tb_sum ^ lr_sum;
+            let at_least_two = (tb_sum & lr_sum) | tb_carry | lr_carry;
+            let mut valency_one = odd & !at_least_two & row_mask;
+            while valency_one != 0 {
+                let x = valency_one.trailing_zeros() as usize;
+                result.push((x, y));
+                valency_one &= valency_one - 1;
+            }
+        }
+        result
+    }
+
+    // Indicate whether any coin has valency 3 or more (a joint, in the paper's terms)
+    pub fn has_joint(self: &SimplePosition) -> bool {
+        let row_mask = low_bits(self.width());
+        (0..self.height()).any(|y| {
+            let (top, bottom) = (self.horizontal_strings[y], self.horizontal_strings[y + 1]);
+            let (left, right) = (self.vertical_strings[y], self.vertical_strings[y] >> 1);
+            let (tb_any, tb_both) = (top | bottom, top & bottom);
+            let (lr_any, lr_both) = (left | right, left & right);
+            (tb_both & lr_any | lr_both & tb_any) & row_mask != 0
+        })
     }
 
     // Move from the square indicated in the direction indicated by the side,
@@ -195,9 +358,9 @@ impl SimplePosition {
     pub fn offset(self: &SimplePosition, x: usize, y: usize, s: Side) -> Option<(usize, usize)> {
         match (x, y, s) {
             (0, _, Side::Left) => None,
-            (x, _, Side::Right) if x == self.width()-1 => None,
+            (x, _, Side::Right) if x + 1 >= self.width() => None,
             (_, 0, Side::Top) => None,
-            (_, y, Side::Bottom) if y == self.height()-1 => None,
+            (_, y, Side::Bottom) if y + 1 >= self.height() => None,
             (x, y, Side::Left) => Some((x-1, y)),
             (x, y, Side::Right) => Some((x+1, y)),
             (x, y, Side::Top) => Some((x, y-1)),
@@ -205,41 +368,69 @@ impl SimplePosition {
         }
     }
 
+    // The string cut by a given move, if the move is on the board
+    fn string_ref(self: &SimplePosition, m: Move) -> Option<StringRef> {
+        if m.x < self.width() && m.y < self.height() {
+            Some(StringRef::from_move(m))
+        } else {
+            None
+        }
+    }
+
+    fn is_string_present(self: &SimplePosition, s: StringRef) -> bool {
+        let row = if s.horizontal { self.horizontal_strings[s.y] } else { self.vertical_strings[s.y] };
+        (row >> s.x) & 1 == 1
+    }
+
+    // Callers must ensure the move is on the board
     fn set_string_value(self: &mut SimplePosition, m: Move, val: bool) {
-        match (m.x, m.y, m.side) {
-            (0, y, Side::Left) => self.left_strings[y] = val,
-            (x, 0, Side::Top) => self.top_strings[x] = val,
-            (x, y, Side::Top) => self.down_strings[x][y-1] = val,
-            (x, y, Side::Bottom) => self.down_strings[x][y] = val,
-            (x, y, Side::Left) => self.right_strings[x-1][y] = val,
-            (x, y, Side::Right) => self.right_strings[x][y] = val,
+        let s = StringRef::from_move(m);
+        let row = if s.horizontal {
+            &mut self.horizontal_strings[s.y]
+        } else {
+            &mut self.vertical_strings[s.y]
+        };
+        if val {
+            *row |= 1 << s.x;
+        } else {
+            *row &= !(1 << s.x);
         }
     }
 
-    fn legal_move_count(self: &SimplePosition) -> usize {
-        self.left_strings.iter().filter(|&&b| b).count() +
-        self.top_strings.iter().filter(|&&b| b).count() +
-        self.down_strings.iter().flat_map(|r| r.iter().filter(|&&b| b)).count() +
-        self.right_strings.iter().flat_map(|r| r.iter().filter(|&&b| b)).count()
+    // Create the image of this position under a given symmetry
+    pub fn transform(self: &SimplePosition, sym: Symmetry) -> SimplePosition {
+        let (width, height) = if sym.transpose {
+            (self.height(), self.width())
+        } else {
+            (self.width(), self.height())
+        };
+        let mut result = SimplePosition::new_end_game(width, height);
+        for m in self.legal_moves() {
+            result.undo_move(sym.apply(m, self.width(), self.height()));
+        }
+        result
     }
+
+    // Replace the Zobrist hash with one generated from a different seed,
+    // keeping it consistent with the strings currently present
+    fn reseed_zhash(self: &mut SimplePosition, extra_seed: usize) {
+        let mut zhash = ZHash::new_seeded(self.width(), self.height(), extra_seed);
+        zhash.toggle_all();
+        for m in self.legal_moves() {
+            zhash.toggle_element(m);
+        }
+        self.zhash = zhash;
+    }
+}
+
+// Bitmask with the lowest n bits set
+fn low_bits(n: usize) -> u64 {
+    if n >= 64 { !0 } else { (1 << n) - 1 }
 }
 
 impl Position<Move> for SimplePosition {
     fn is_legal_move(self: &SimplePosition, m: Move) -> bool {
-        if m.x >= self.width() {
-            return false;
-        }
-        if m.y >= self.height() {
-            return false;
-        }
-        match (m.x, m.y, m.side) {
-            (0, y, Side::Left) => self.left_strings[y],
-            (x, 0, Side::Top) => self.top_strings[x],
-            (x, y, Side::Top) => self.down_strings[x][y-1],
-            (x, y, Side::Bottom) => self.down_strings[x][y],
-            (x, y, Side::Left) => self.right_strings[x-1][y],
-            (x, y, Side::Right) => self.right_strings[x][y],
-        }
+        self.string_ref(m).is_some_and(|s| self.is_string_present(s))
     }
 
     fn would_capture(self: &SimplePosition, m: Move) -> usize {
@@ -259,34 +450,14 @@ impl Position<Move> for SimplePosition {
         if !self.is_legal_move(m) {
             panic!("Illegal move {}, pos:\n{}", m, self);
         }
+        let captures = self.would_capture(m);
         self.set_string_value(m, false);
-        let mut captures = if self.is_captured(m.x, m.y) { 1 } else { 0 };
-        if m.side == Side::Left && m.x > 0 {
-            if self.is_captured(m.x-1, m.y) {
-                captures += 1
-            }
-        }
-        if m.side == Side::Right && m.x < self.width()-1 {
-            if self.is_captured(m.x+1, m.y) {
-                captures += 1
-            }
-        }
-        if m.side == Side::Top && m.y > 0 {
-            if self.is_captured(m.x, m.y-1) {
-                captures += 1
-            }
-        }
-        if m.side == Side::Bottom && m.y < self.height()-1 {
-            if self.is_captured(m.x, m.y+1) {
-                captures += 1
-            }
-        }
         let end_of_game = self.is_end_of_game();
         self.zhash.toggle_element(m);
         MoveOutcome {
             coins_captured: captures,
             end_of_turn: captures == 0 || end_of_game,
-            end_of_game: end_of_game,
+            end_of_game,
         }
     }
 
@@ -295,41 +466,46 @@ impl Position<Move> for SimplePosition {
         self.zhash.toggle_element(m);
     }
 
-    fn is_end_of_game(self: &SimplePosition) -> bool {
-        if self.left_strings.iter().any(|&b| b) {
-            return false;
-        }
-        if self.top_strings.iter().any(|&b| b) {
-            return false;
+    fn try_undo_move(self: &mut SimplePosition, m: Move) -> Result<(), Error> {
+        match self.string_ref(m) {
+            Some(s) if !self.is_string_present(s) => {
+                self.undo_move(m);
+                Ok(())
+            },
+            _ => Err(Error::MoveNotMade(m.to_string())),
         }
-        if self.down_strings.iter().any(|col| col.iter().any(|&b| b)) {
-            return false;
-        }
-        if self.right_strings.iter().any(|col| col.iter().any(|&b| b)) {
-            return false;
-        }
-        true
+    }
+
+    fn is_end_of_game(self: &SimplePosition) -> bool {
+        self.horizontal_strings.iter().all(|&row| row == 0)
+            && self.vertical_strings.iter().all(|&row| row == 0)
+    }
+
+    fn legal_move_count(self: &SimplePosition) -> usize {
+        self.horizontal_strings.iter().chain(self.vertical_strings.iter())
+            .map(|row| row.count_ones() as usize).sum()
     }
 
     fn legal_moves(self: &SimplePosition) -> Vec<Move> {
         let mut result: Vec<Move> = Vec::with_capacity(self.legal_move_count());
-        for (x, &b) in self.top_strings.iter().enumerate() {
-            if b {
-                result.push(Move{ x: x, y: 0, side: Side::Top });
+        let top_row = self.horizontal_strings[0];
+        for x in 0..self.width() {
+            if (top_row >> x) & 1 == 1 {
+                result.push(Move::new(x, 0, Side::Top));
             }
         }
-        for (y, &b) in self.left_strings.iter().enumerate() {
-            if b {
-                result.push(Move{ x: 0, y: y, side: Side::Left });
+        for (y, &row) in self.vertical_strings.iter().enumerate() {
+            if row & 1 == 1 {
+                result.push(Move::new(0, y, Side::Left));
             }
         }
         for x in 0..self.width() {
             for y in 0..self.height() {
-                if self.down_strings[x][y] {
-                    result.push(Move{ x: x, y: y, side: Side::Bottom });
+                if (self.horizontal_strings[y + 1] >> x) & 1 == 1 {
+                    result.push(Move::new(x, y, Side::Bottom));
                 }
-                if self.right_strings[x][y] {
-                    result.push(Move{ x: x, y: y, side: Side::Right })
+                if (self.vertical_strings[y] >> (x + 1)) & 1 == 1 {
+                    result.push(Move::new(x, y, Side::Right));
                 }
             }
         }
@@ -351,25 +527,25 @@ impl Position<Move> for SimplePosition {
     fn zhash(self: &SimplePosition) -> usize {
         self.zhash.current_value()
     }
+
+    fn canonical_zhash(self: &SimplePosition) -> usize {
+        self.zhash.canonical_value()
+    }
+
+    fn canonical_move(self: &SimplePosition, m: Move) -> Move {
+        self.zhash.canonical_symmetry().apply(m, self.width(), self.height())
+    }
+
+    fn move_from_canonical(self: &SimplePosition, m: Move) -> Move {
+        self.zhash.canonical_symmetry().inverse().apply(m, self.width(), self.height())
+    }
 }
 
 impl PartialEq for SimplePosition {
     fn eq(self: &SimplePosition, other: &SimplePosition) -> bool {
-        if self.width() != other.width() || self.height() != other.height() {
-            return false;
-        }
-        for x in 0..self.width() {
-            for y in 0..self.height() {
-                for s in Side::all() {
-                    let legal_here = self.is_legal_move(Move{x: x, y: y, side: s});
-                    let legal_there = other.is_legal_move(Move{x: x, y: y, side: s});
-                    if legal_here != legal_there {
-                        return false;
-                    }
-                }
-            }
-        }
-        true
+        self.width == other.width
+            && self.horizontal_strings == other.horizontal_strings
+            && self.vertical_strings == other.vertical_strings
     }
 }
 
@@ -377,100 +553,244 @@ impl Eq for SimplePosition {}
 
 impl fmt::Display for SimplePosition {
     fn fmt(self: &SimplePosition, f: &mut fmt::Formatter) -> fmt::Result {
+        let is_set = |row: u64, x: usize| (row >> x) & 1 == 1;
         write!(f, "  ")?;
         for i in 0..self.width() {
             write!(f, " {}", i % 10)?;
         }
         write!(f, "\n  ")?;
-        for &b in self.top_strings.iter() {
-            write!(f, "+{}", if b { " " } else { "-" })?;
+        for i in 0..self.width() {
+            write!(f, "+{}", if is_set(self.horizontal_strings[0], i) { " " } else { "-" })?;
         }
-        write!(f, "+\n")?;
+        writeln!(f, "+")?;
         for j in 0..self.height() {
-            write!(f, "{} {}", j % 10, if self.left_strings[j] { " " } else { "|" })?;
+            let v_row = self.vertical_strings[j];
+            write!(f, "{} {}", j % 10, if is_set(v_row, 0) { " " } else { "|" })?;
             for i in 0..self.width() {
-                write!(f, " {}", if self.right_strings[i][j] { " " } else { "|" })?;
+                write!(f, " {}", if is_set(v_row, i + 1) { " " } else { "|" })?;
             }
             write!(f, "\n  ")?;
             for i in 0..self.width() {
-                write!(f, "+{}", if self.down_strings[i][j] { " " } else { "-" })?;
+                write!(f, "+{}", if is_set(self.horizontal_strings[j + 1], i) { " " } else { "-" })?;
             }
-            write!(f, "+\n")?;
+            writeln!(f, "+")?;
         }
         Ok(())
     }
 }
 
-// Struct to encapsulate Zobrist hash for positions
-// It has an internal structure mirroring the position, one integer per element
+// One of the symmetries of a rectangular board: reflection in the x and/or y axis,
+// followed (on a square board only) by transposition, i.e. reflection in the diagonal.
+// Together these generate all the rotations and reflections of the board.
 #[derive(Clone)]
-struct ZHash {
-    current_val: usize,
-    top_strings: Vec<usize>,
-    left_strings: Vec<usize>,
-    right_strings: Vec<Vec<usize>>,
-    down_strings: Vec<Vec<usize>>,
+#[derive(Copy)]
+#[derive(Eq)]
+#[derive(Hash)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub struct Symmetry {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub transpose: bool,
 }
 
-impl ZHash {
-    fn new(width: usize, height: usize) -> ZHash {
-        ZHash::new_seeded(width, height, 0)
+impl Symmetry {
+    pub fn identity() -> Symmetry {
+        Symmetry{flip_x: false, flip_y: false, transpose: false}
     }
 
-    fn new_seeded(width: usize, height: usize, extra_seed: usize) -> ZHash {
+    // All symmetries of a board of a given size, starting with the identity
+    pub fn all(width: usize, height: usize) -> Vec<Symmetry> {
+        let transposes: &[bool] = if width == height { &[false, true] } else { &[false] };
+        let mut result = Vec::with_capacity(8);
+        for &transpose in transposes {
+            for &flip_y in &[false, true] {
+                for &flip_x in &[false, true] {
+                    result.push(Symmetry{flip_x, flip_y, transpose});
+                }
+            }
+        }
+        result
+    }
+
+    pub fn inverse(self: &Symmetry) -> Symmetry {
+        // Transposing swaps the axes, so undoing a flip after transposition
+        // means flipping the other axis
+        if self.transpose {
+            Symmetry{flip_x: self.flip_y, flip_y: self.flip_x, transpose: true}
+        } else {
+            *self
+        }
+    }
+
+    // Map a move on a board of a given size to the corresponding move on the transformed board
+    pub fn apply(self: &Symmetry, m: Move, width: usize, height: usize) -> Move {
+        let (mut x, mut y, mut side) = (m.x, m.y, m.side);
+        if self.flip_x {
+            x = width - 1 - x;
+            side = match side { Side::Left | Side::Right => side.opposite(), s => s };
+        }
+        if self.flip_y {
+            y = height - 1 - y;
+            side = match side { Side::Top | Side::Bottom => side.opposite(), s => s };
+        }
+        if self.transpose {
+            std::mem::swap(&mut x, &mut y);
+            side = match side {
+                Side::Top => Side::Left,
+                Side::Left => Side::Top,
+                Side::Bottom => Side::Right,
+                Side::Right => Side::Bottom,
+            };
+        }
+        Move::new(x, y, side)
+    }
+}
+
+// Random elements for the Zobrist hashes of all positions of a given size and seed.
+// For each string there is one element per symmetry of the board, being the element
+// for the string it maps to under that symmetry, so that each hash value can be
+// maintained as if the transformed position were being played in parallel.
+struct ZElements {
+    symmetries: Vec<Symmetry>,
+    base_val: usize,
+    elements: Vec<usize>,
+}
+
+impl ZElements {
+    fn new(width: usize, height: usize, extra_seed: usize) -> ZElements {
         let mut seed: [u8; 32] = [0; 32];
         seed[0] = width as u8;
         seed[1] = height as u8;
         seed[2] = extra_seed as u8;
         let mut r: StdRng = SeedableRng::from_seed(seed);
-        let mut top_strings: Vec<usize> = Vec::with_capacity(width);
-        let mut left_strings: Vec<usize> = Vec::with_capacity(height);
-        let mut right_strings: Vec<Vec<usize>> = Vec::with_capacity(width);
-        let mut down_strings: Vec<Vec<usize>> = Vec::with_capacity(width);
-
-        for i in 0..width {
-            top_strings.push(r.gen());
-            right_strings.push(Vec::with_capacity(height));
-            down_strings.push(Vec::with_capacity(height));
-            for _ in 0..height {
-                right_strings[i].push(r.gen());
-                down_strings[i].push(r.gen());
+        let string_count = StringRef::count(width, height);
+        let base_elements: Vec<usize> = (0..string_count).map(|_| r.gen()).collect();
+        let symmetries = Symmetry::all(width, height);
+        let mut elements = Vec::with_capacity(string_count * symmetries.len());
+        for i in 0..string_count {
+            let m = StringRef::from_index(i, width, height).to_move(width, height);
+            for sym in &symmetries {
+                let target = StringRef::from_move(sym.apply(m, width, height));
+                elements.push(base_elements[target.index(width, height)]);
             }
         }
-        for _ in 0..height {
-            left_strings.push(r.gen());
+        ZElements{symmetries, base_val: r.gen(), elements}
+    }
+
+    // Share the elements between all positions of the same size and seed,
+    // as they are expensive to generate
+    fn shared(width: usize, height: usize, extra_seed: usize) -> Arc<ZElements> {
+        thread_local! {
+            static CACHE: RefCell<HashMap<(usize, usize, usize), Arc<ZElements>>> = RefCell::new(HashMap::new());
         }
+        CACHE.with(|cache| {
+            cache.borrow_mut().entry((width, height, extra_seed))
+                .or_insert_with(|| Arc::new(ZElements::new(width, height, extra_seed)))
+                .clone()
+        })
+    }
+}
+
+// Struct to encapsulate Zobrist hash for positions
+// It has an internal structure mirroring the bitboards of the position, one integer per string.
+// The hash value is the XOR of a random base value and the elements for all strings which
+// have been removed, so it depends only on the strings present and not on how the position
+// was constructed.
+// Alongside it are the hashes of the position's images under each symmetry of the board,
+// the smallest of which is a canonical hash shared by all of those images.
+#[derive(Clone)]
+struct ZHash {
+    width: usize,
+    height: usize,
+    values: Vec<usize>,
+    elements: Arc<ZElements>,
+}
+
+impl ZHash {
+    fn new(width: usize, height: usize) -> ZHash {
+        ZHash::new_seeded(width, height, 0)
+    }
 
+    fn new_seeded(width: usize, height: usize, extra_seed: usize) -> ZHash {
+        let elements = ZElements::shared(width, height, extra_seed);
         ZHash{
-            current_val: r.gen(),
-            top_strings: top_strings,
-            left_strings: left_strings,
-            right_strings: right_strings,
-            down_strings: down_strings,
+            width,
+            height,
+            values: vec![elements.base_val; elements.symmetries.len()],
+            elements,
         }
     }
 
     fn current_value(self: &ZHash) -> usize {
-        self.current_val
+        self.values[0]
+    }
+
+    fn canonical_value(self: &ZHash) -> usize {
+        *self.values.iter().min().unwrap()
+    }
+
+    // Symmetry taking the position to the orientation with the canonical hash value
+    fn canonical_symmetry(self: &ZHash) -> Symmetry {
+        let canonical = self.canonical_value();
+        let idx = self.values.iter().position(|&v| v == canonical).unwrap();
+        self.elements.symmetries[idx]
     }
 
     fn toggle_element(self: &mut ZHash, m: Move) {
-        match (m.x, m.y, m.side) {
-            (0, y, Side::Left) => self.current_val ^= self.left_strings[y],
-            (x, 0, Side::Top) => self.current_val ^= self.top_strings[x],
-            (x, y, Side::Left) => self.current_val ^= self.right_strings[x-1][y],
-            (x, y, Side::Right) => self.current_val ^= self.right_strings[x][y],
-            (x, y, Side::Bottom) => self.current_val ^= self.down_strings[x][y],
-            (x, y, Side::Top) => self.current_val ^= self.down_strings[x][y-1],
+        let s = StringRef::from_move(m);
+        self.toggle_string(s);
+    }
+
+    fn toggle_string(self: &mut ZHash, s: StringRef) {
+        let n = self.values.len();
+        let start = s.index(self.width, self.height) * n;
+        for (v, e) in self.values.iter_mut().zip(&self.elements.elements[start..start + n]) {
+            *v ^= e;
+        }
+    }
+
+    fn toggle_all(self: &mut ZHash) {
+        for i in 0..StringRef::count(self.width, self.height) {
+            self.toggle_string(StringRef::from_index(i, self.width, self.height));
         }
     }
 }
 
+// Zobrist hashes are already uniformly distributed, so tables keyed on them
+// can use the hash value directly rather than hashing it again.
+#[derive(Default)]
+pub struct ZHasher {
+    value: u64,
+}
+
+impl Hasher for ZHasher {
+    fn finish(self: &ZHasher) -> u64 {
+        self.value
+    }
+
+    fn write(self: &mut ZHasher, bytes: &[u8]) {
+        for &b in bytes {
+            self.value = self.value.rotate_left(8) ^ (b as u64);
+        }
+    }
+
+    fn write_usize(self: &mut ZHasher, i: usize) {
+        self.value ^= i as u64;
+    }
+}
+
+// Map keyed on Position::zhash or Position::canonical_zhash values
+pub type ZHashMap<V> = HashMap<usize, V, BuildHasherDefault<ZHasher>>;
+
 // Representation of a position composed of multiple rectangular dots-and-boxes
 // positions. This allows some additional strings-and-coins positions to be
 // represented, such as the one-large-chain-multiple-3-chains positions from
 // the paper.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "CompoundPositionRecord", from = "CompoundPositionRecord"))]
 pub struct CompoundPosition {
     pub(crate) parts: Vec<SimplePosition>,
 }
@@ -481,6 +801,8 @@ pub struct CompoundPosition {
 #[derive(PartialEq)]
 #[derive(Eq)]
 #[derive(Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct CPosMove {
     pub part: usize,
     pub m: Move,
@@ -501,8 +823,8 @@ impl fmt::Display for CPosMove {
 impl CompoundPosition {
     pub fn new(mut parts: Vec<SimplePosition>) -> CompoundPosition {
         // We need different components to have different zhashes even if identical
-        for idx in 0..parts.len() {
-            parts[idx].zhash = ZHash::new_seeded(parts[idx].width(), parts[idx].height(), idx);
+        for (idx, part) in parts.iter_mut().enumerate() {
+            part.reseed_zhash(idx);
         }
         CompoundPosition{ parts: parts }
     }
@@ -533,10 +855,21 @@ impl Position<CPosMove> for CompoundPosition {
         self.parts[m.part].undo_move(m.m)
     }
 
+    fn try_undo_move(self: &mut CompoundPosition, m: CPosMove) -> Result<(), Error> {
+        match self.parts.get_mut(m.part) {
+            Some(p) => p.try_undo_move(m.m).map_err(|_| Error::MoveNotMade(m.to_string())),
+            None => Err(Error::MoveNotMade(m.to_string())),
+        }
+    }
+
     fn is_end_of_game(self: &CompoundPosition) -> bool {
         self.parts.iter().all(|p| p.is_end_of_game())
     }
 
+    fn legal_move_count(self: &CompoundPosition) -> usize {
+        self.parts.iter().map(|p| p.legal_move_count()).sum()
+    }
+
     fn legal_moves(self: &CompoundPosition) -> Vec<CPosMove> {
         let sub_moves: Vec<Vec<Move>> = self.parts.iter().map(|p| p.legal_moves()).collect();
         let mut result: Vec<CPosMove> = Vec::with_capacity(sub_moves.iter().map(|ms| ms.len()).sum());
@@ -559,6 +892,23 @@ impl Position<CPosMove> for CompoundPosition {
         }
         result
     }
+
+    fn canonical_zhash(self: &CompoundPosition) -> usize {
+        // Each part can be reflected or rotated independently of the others
+        let mut result = 0;
+        for part in self.parts.iter() {
+            result ^= part.canonical_zhash();
+        }
+        result
+    }
+
+    fn canonical_move(self: &CompoundPosition, m: CPosMove) -> CPosMove {
+        CPosMove{part: m.part, m: self.parts[m.part].canonical_move(m.m)}
+    }
+
+    fn move_from_canonical(self: &CompoundPosition, m: CPosMove) -> CPosMove {
+        CPosMove{part: m.part, m: self.parts[m.part].move_from_canonical(m.m)}
+    }
 }
 
 impl PartialEq for CompoundPosition {
@@ -588,6 +938,205 @@ impl fmt::Display for CompoundPosition {
     }
 }
 
+// Outcome of a finished game, with players identified by their index in Game::players
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub enum GameResult {
+    Win(usize),
+    Draw,
+}
+
+// A move as played in a game: who made it, and what happened as a result
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+pub struct GameMove<M> {
+    pub m: M,
+    pub player: usize,
+    pub outcome: MoveOutcome,
+}
+
+// A game in progress between two players, wrapping the position being played on.
+// Players are numbered 0 and 1, with player 0 moving first. Only boxes captured
+// during the game count towards the players' scores, so any already captured
+// in the starting position are disregarded.
+pub struct Game<M, P> {
+    pos: P,
+    players: [String; 2],
+    scores: [usize; 2],
+    to_move: usize,
+    history: Vec<GameMove<M>>,
+    // Moves taken back with undo, most recently undone last
+    undone: Vec<M>,
+}
+
+impl <M: Copy + fmt::Display, P: Position<M>> Game<M, P> {
+    pub fn new(pos: P, first_player: &str, second_player: &str) -> Game<M, P> {
+        Game{
+            pos: pos,
+            players: [first_player.to_string(), second_player.to_string()],
+            scores: [0, 0],
+            to_move: 0,
+            history: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    pub fn position(self: &Game<M, P>) -> &P {
+        &self.pos
+    }
+
+    pub fn into_position(self: Game<M, P>) -> P {
+        self.pos
+    }
+
+    pub fn players(self: &Game<M, P>) -> &[String; 2] {
+        &self.players
+    }
+
+    // Number of boxes captured so far by the given player
+    pub fn score(self: &Game<M, P>, player: usize) -> usize {
+        self.scores[player]
+    }
+
+    // Index of the player whose turn it is
+    pub fn to_move(self: &Game<M, P>) -> usize {
+        self.to_move
+    }
+
+    // All moves made so far (excluding any which have been undone), in order
+    pub fn moves(self: &Game<M, P>) -> &[GameMove<M>] {
+        &self.history
+    }
+
+    pub fn is_over(self: &Game<M, P>) -> bool {
+        self.pos.is_end_of_game()
+    }
+
+    // The result of the game, if it is over
+    pub fn result(self: &Game<M, P>) -> Option<GameResult> {
+        if !self.is_over() {
+            return None;
+        }
+        Some(if self.scores[0] > self.scores[1] {
+            GameResult::Win(0)
+        } else if self.scores[1] > self.scores[0] {
+            GameResult::Win(1)
+        } else {
+            GameResult::Draw
+        })
+    }
+
+    // Make a move for the player whose turn it is. Making a move discards
+    // any moves which were available to redo.
+    pub fn make_move(self: &mut Game<M, P>, m: M) -> Result<MoveOutcome, Error> {
+        let outcome = self.play(m)?;
+        self.undone.clear();
+        Ok(outcome)
+    }
+
+    fn play(self: &mut Game<M, P>, m: M) -> Result<MoveOutcome, Error> {
+        let player = self.to_move;
+        let outcome = self.pos.try_make_move(m)?;
+        self.scores[player] += outcome.coins_captured;
+        if outcome.end_of_turn {
+            self.to_move = 1 - player;
+        }
+        self.history.push(GameMove{m: m, player: player, outcome: outcome});
+        Ok(outcome)
+    }
+
+    // Take back the last move made, if any, returning it
+    pub fn undo(self: &mut Game<M, P>) -> Option<M> {
+        let last = self.history.pop()?;
+        self.pos.undo_move(last.m);
+        self.scores[last.player] -= last.outcome.coins_captured;
+        self.to_move = last.player;
+        self.undone.push(last.m);
+        Some(last.m)
+    }
+
+    // Take back a given move, which must be equivalent to the last one made
+    pub fn undo_move(self: &mut Game<M, P>, m: M) -> Result<(), Error> {
+        match self.history.last() {
+            Some(last) if self.pos.moves_equivalent(last.m, m) => {
+                self.undo();
+                Ok(())
+            },
+            Some(last) => Err(Error::NotLastMove(last.m.to_string())),
+            None => Err(Error::NothingToUndo),
+        }
+    }
+
+    // Make the last move which was undone again, if any, returning it
+    pub fn redo(self: &mut Game<M, P>) -> Option<GameMove<M>> {
+        let m = self.undone.pop()?;
+        self.play(m).expect("Undone move should be legal to redo");
+        self.history.last().copied()
+    }
+
+    pub fn can_redo(self: &Game<M, P>) -> bool {
+        !self.undone.is_empty()
+    }
+}
+
+// Serialised form of a SimplePosition. Positions are rebuilt by drawing the lines
+// on a new board, so that their Zobrist hashes match those of the originals.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+struct SimplePositionRecord {
+    width: usize,
+    height: usize,
+    lines: Vec<Move>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SimplePosition> for SimplePositionRecord {
+    fn from(pos: SimplePosition) -> SimplePositionRecord {
+        let lines = SimplePosition::new_game(pos.width(), pos.height()).legal_moves().into_iter()
+            .filter(|&m| !pos.is_legal_move(m)).collect();
+        SimplePositionRecord{width: pos.width(), height: pos.height(), lines: lines}
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SimplePositionRecord> for SimplePosition {
+    type Error = Error;
+
+    fn try_from(record: SimplePositionRecord) -> Result<SimplePosition, Error> {
+        let mut pos = SimplePosition::try_new_game(record.width, record.height)?;
+        for m in record.lines {
+            pos.try_make_move(m)?;
+        }
+        Ok(pos)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+struct CompoundPositionRecord {
+    parts: Vec<SimplePosition>,
+}
+
+#[cfg(feature = "serde")]
+impl From<CompoundPosition> for CompoundPositionRecord {
+    fn from(pos: CompoundPosition) -> CompoundPositionRecord {
+        CompoundPositionRecord{parts: pos.parts}
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<CompoundPositionRecord> for CompoundPosition {
+    fn from(record: CompoundPositionRecord) -> CompoundPosition {
+        CompoundPosition::new(record.parts)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::game::*;
@@ -937,6 +1486,131 @@ mod tests {
         assert!(intersect.is_empty());
     }
 
+    #[test]
+    fn zhashes_independent_of_construction() {
+        // Building a position up from an empty board must give the same hash
+        // as cutting strings from a full one
+        let target = p50_bottomleft();
+        let mut pos = SimplePosition::new_end_game(target.width(), target.height());
+        for m in target.legal_moves() {
+            pos.undo_move(m);
+        }
+        assert_eq!(true, pos.eq(&target));
+        assert_eq!(target.zhash(), pos.zhash());
+
+        let compound = CompoundPosition::new(vec!(make_chain(3), make_chain(3)));
+        let mut rebuilt = CompoundPosition::new(vec!(
+            SimplePosition::new_game(3, 1), SimplePosition::new_game(3, 1)));
+        for p in 0..2 {
+            for x in 0..3 {
+                rebuilt.make_move(CPosMove::new(p, x, 0, Side::Top));
+                rebuilt.make_move(CPosMove::new(p, x, 0, Side::Bottom));
+            }
+        }
+        assert_eq!(compound.zhash(), rebuilt.zhash());
+    }
+
+    #[test]
+    fn capturable_coins() {
+        let mut pos = SimplePosition::new_game(3, 2);
+        assert!(pos.capturable_coins().is_empty());
+        pos.make_move(Move::new(2, 1, Side::Right));
+        pos.make_move(Move::new(2, 1, Side::Bottom));
+        pos.make_move(Move::new(2, 1, Side::Left));
+        assert_eq!(vec!((2, 1)), pos.capturable_coins());
+        pos.make_move(Move::new(0, 0, Side::Top));
+        pos.make_move(Move::new(0, 0, Side::Left));
+        pos.make_move(Move::new(0, 0, Side::Right));
+        assert_eq!(vec!((0, 0), (2, 1)), pos.capturable_coins());
+        pos.make_move(Move::new(0, 0, Side::Bottom));
+        assert_eq!(vec!((2, 1)), pos.capturable_coins());
+        for (x, y) in pos.capturable_coins() {
+            assert_eq!(1, pos.valency(x, y));
+        }
+    }
+
+    #[test]
+    fn wide_board() {
+        let mut pos = SimplePosition::new_game(MAX_WIDTH, 2);
+        assert_eq!(MAX_WIDTH * 3 + (MAX_WIDTH + 1) * 2, pos.legal_moves().len());
+        let m = Move::new(MAX_WIDTH - 1, 1, Side::Right);
+        assert_eq!(true, pos.is_legal_move(m));
+        assert_eq!(4, pos.valency(MAX_WIDTH - 1, 1));
+        pos.make_move(m);
+        assert_eq!(false, pos.is_legal_move(m));
+        assert_eq!(3, pos.valency(MAX_WIDTH - 1, 1));
+    }
+
+    #[test]
+    fn symmetries() {
+        assert_eq!(4, Symmetry::all(3, 2).len());
+        assert_eq!(8, Symmetry::all(3, 3).len());
+        assert_eq!(Symmetry::identity(), Symmetry::all(3, 3)[0]);
+
+        let pos = SimplePosition::new_game(3, 3);
+        let rotate = Symmetry{flip_x: true, flip_y: false, transpose: true};
+        assert_eq!(Move::new(0, 2, Side::Left), rotate.apply(Move::new(0, 0, Side::Top), 3, 3));
+        assert_eq!(Move::new(1, 0, Side::Top), rotate.apply(Move::new(2, 1, Side::Right), 3, 3));
+        for (width, height) in [(3, 3), (4, 2)] {
+            for sym in Symmetry::all(width, height) {
+                for m in SimplePosition::new_game(width, height).legal_moves() {
+                    let image = sym.apply(m, width, height);
+                    assert_eq!(m, sym.inverse().apply(image, width, height), "{:?} {}", sym, m);
+                }
+            }
+        }
+        assert_eq!(true, pos.eq(&pos.transform(rotate)));
+    }
+
+    #[test]
+    fn canonical_zhashes() {
+        for pos in [p50(), ex3p1(), p50_bottomleft(), SimplePosition::new_game(4, 4)] {
+            let mut hashes = HashSet::new();
+            for sym in Symmetry::all(pos.width(), pos.height()) {
+                let image = pos.transform(sym);
+                hashes.insert(image.canonical_zhash());
+                // Moves must map onto the same string in every orientation
+                let m = pos.legal_moves()[0];
+                let image_m = sym.apply(m, pos.width(), pos.height());
+                let canonical_m = pos.canonical_move(m);
+                assert_eq!(m, pos.move_from_canonical(canonical_m));
+                // If the position is itself symmetric, the move may come back as its
+                // image under one of the symmetries which leave the position unchanged
+                let round_trip = pos.move_from_canonical(image.canonical_move(image_m));
+                assert_eq!(true, pos.is_legal_move(round_trip));
+                let symmetric_images: Vec<Move> = Symmetry::all(pos.width(), pos.height()).into_iter()
+                    .filter(|&sym2| pos.eq(&pos.transform(sym2)))
+                    .map(|sym2| sym2.apply(m, pos.width(), pos.height()))
+                    .collect();
+                assert_eq!(true, symmetric_images.iter().any(|&m2| pos.moves_equivalent(m2, round_trip)),
+                           "{:?} {} {}", sym, m, round_trip);
+            }
+            assert_eq!(1, hashes.len());
+        }
+
+        // Reflections of distinct positions should still be distinguished
+        let mut pos1 = SimplePosition::new_game(3, 3);
+        let mut pos2 = SimplePosition::new_game(3, 3);
+        pos1.make_move(Move::new(0, 0, Side::Top));
+        pos2.make_move(Move::new(1, 0, Side::Top));
+        assert!(pos1.canonical_zhash() != pos2.canonical_zhash());
+        pos2.undo_move(Move::new(1, 0, Side::Top));
+        pos2.make_move(Move::new(2, 2, Side::Right));
+        assert_eq!(pos1.canonical_zhash(), pos2.canonical_zhash());
+    }
+
+    #[test]
+    fn compound_canonical_zhashes() {
+        let mut pos1 = one_long_multi_three(2, 4);
+        let mut pos2 = one_long_multi_three(2, 4);
+        pos1.make_move(CPosMove::new(1, 0, 0, Side::Left));
+        pos2.make_move(CPosMove::new(1, 2, 0, Side::Right));
+        assert!(pos1.zhash() != pos2.zhash());
+        assert_eq!(pos1.canonical_zhash(), pos2.canonical_zhash());
+        let m = CPosMove::new(1, 1, 0, Side::Right);
+        assert_eq!(m, pos1.move_from_canonical(pos1.canonical_move(m)));
+    }
+
     #[test]
     fn end_position() {
         let (width, height) = (3, 4);
@@ -1067,4 +1741,102 @@ mod tests {
         assert_eq!(legals.len() * 2 + 1, hashes.len());
         assert_eq!(false, hashes.contains(&0));
     }
+
+    #[test]
+    fn game_scores_and_turns() {
+        let mut game = Game::new(SimplePosition::new_game(2, 1), "Alice", "Bob");
+        assert_eq!(0, game.to_move());
+        let turns = [
+            (Move::new(0, 0, Side::Top), 1, [0, 0]),
+            (Move::new(0, 0, Side::Bottom), 0, [0, 0]),
+            (Move::new(0, 0, Side::Left), 1, [0, 0]),
+            (Move::new(0, 0, Side::Right), 1, [0, 1]),
+            (Move::new(1, 0, Side::Top), 0, [0, 1]),
+            (Move::new(1, 0, Side::Bottom), 1, [0, 1]),
+        ];
+        for &(m, to_move, scores) in &turns {
+            assert_eq!(None, game.result());
+            game.make_move(m).unwrap();
+            assert_eq!(to_move, game.to_move(), "After {}", m);
+            assert_eq!(scores, [game.score(0), game.score(1)], "After {}", m);
+        }
+        let outcome = game.make_move(Move::new(1, 0, Side::Right)).unwrap();
+        assert_eq!(true, outcome.end_of_game);
+        assert_eq!(2, game.score(1));
+        assert_eq!(Some(GameResult::Win(1)), game.result());
+        assert_eq!(7, game.moves().len());
+        assert_eq!(vec!(0, 1, 0, 1, 1, 0, 1), game.moves().iter().map(|gm| gm.player).collect::<Vec<usize>>());
+        assert_eq!("Bob", game.players()[1]);
+    }
+
+    #[test]
+    fn game_illegal_move() {
+        let mut game = Game::new(SimplePosition::new_game(2, 1), "Alice", "Bob");
+        game.make_move(Move::new(0, 0, Side::Right)).unwrap();
+        // The same string seen from the other coin
+        assert!(game.make_move(Move::new(1, 0, Side::Left)).is_err());
+        assert_eq!(1, game.moves().len());
+        assert_eq!(1, game.to_move());
+    }
+
+    #[test]
+    fn game_undo_redo() {
+        let mut game = Game::new(make_chain(2), "Alice", "Bob");
+        assert_eq!(None, game.undo());
+        game.make_move(Move::new(0, 0, Side::Left)).unwrap();
+        game.make_move(Move::new(0, 0, Side::Right)).unwrap();
+        game.make_move(Move::new(1, 0, Side::Right)).unwrap();
+        assert_eq!(Some(GameResult::Win(1)), game.result());
+
+        assert_eq!(Some(Move::new(1, 0, Side::Right)), game.undo());
+        assert_eq!(Some(Move::new(0, 0, Side::Right)), game.undo());
+        assert_eq!(None, game.result());
+        assert_eq!(1, game.to_move());
+        assert_eq!(0, game.score(1));
+        assert_eq!(true, game.position().is_legal_move(Move::new(0, 0, Side::Right)));
+
+        let redone = game.redo().unwrap();
+        assert_eq!(Move::new(0, 0, Side::Right), redone.m);
+        assert_eq!(1, redone.player);
+        assert_eq!(1, game.score(1));
+        assert_eq!(true, game.can_redo());
+
+        // A new move replaces whatever could have been redone
+        game.undo();
+        game.make_move(Move::new(1, 0, Side::Right)).unwrap();
+        assert_eq!(false, game.can_redo());
+        assert!(game.redo().is_none());
+        assert_eq!(2, game.moves().len());
+    }
+
+    #[test]
+    fn fallible_moves() {
+        let mut pos = SimplePosition::new_game(2, 2);
+        let m = Move::new(1, 1, Side::Right);
+        assert_eq!(Err(Error::MoveNotMade(m.to_string())), pos.try_undo_move(m));
+        assert_eq!(true, pos.try_make_move(m).is_ok());
+        assert_eq!(Err(Error::IllegalMove(m.to_string())), pos.try_make_move(m));
+        assert_eq!(Ok(()), pos.try_undo_move(m));
+        assert_eq!(true, pos.eq(&SimplePosition::new_game(2, 2)));
+
+        // Moves off the board are reported rather than panicking
+        for &m in &[Move::new(2, 0, Side::Top), Move::new(0, 2, Side::Left), Move::new(64, 0, Side::Right)] {
+            assert_eq!(Err(Error::IllegalMove(m.to_string())), pos.try_make_move(m));
+            assert_eq!(Err(Error::MoveNotMade(m.to_string())), pos.try_undo_move(m));
+        }
+
+        let mut pos = CompoundPosition::new(vec!(make_chain(2)));
+        let m = CPosMove::new(1, 0, 0, Side::Left);
+        assert_eq!(Err(Error::IllegalMove(m.to_string())), pos.try_make_move(m));
+        assert_eq!(Err(Error::MoveNotMade(m.to_string())), pos.try_undo_move(m));
+    }
+
+    #[test]
+    fn degenerate_boards() {
+        assert_eq!(Err(Error::BoardTooWide(MAX_WIDTH + 1)), SimplePosition::try_new_game(MAX_WIDTH + 1, 1).map(|_| ()));
+        let pos = SimplePosition::new_game(0, 0);
+        assert_eq!(None, pos.offset(0, 0, Side::Right));
+        assert_eq!(None, pos.offset(0, 0, Side::Bottom));
+        assert_eq!(true, pos.is_end_of_game());
+    }
 }
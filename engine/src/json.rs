@@ -0,0 +1,145 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::cli;
+use crate::error::Error;
+use crate::eval::{self, EvalTable};
+use crate::game::{Position, CompoundPosition, CPosMove};
+use crate::nimstring::{self, NimstringTable, Value};
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufReader;
+
+// Analysis of a position as structured data, for programs which would rather not
+// read the text printed by the CLI
+
+#[derive(Serialize)]
+pub struct MoveAnalysis {
+    #[serde(rename = "move")]
+    pub m: CPosMove,
+    pub value: isize,
+    // Missing if the nimstring search treated the move as equivalent to another one
+    pub nimstring_value: Option<Value>,
+}
+
+#[derive(Serialize)]
+pub struct Analysis {
+    pub position: CompoundPosition,
+    pub value: isize,
+    pub best_move: Option<CPosMove>,
+    pub nimstring_value: Value,
+    pub moves: Vec<MoveAnalysis>,
+}
+
+// Evaluate a position, its nimstring value and every move in it, as eval, nv and evalall do
+pub fn analyse(pos: &CompoundPosition, threads: usize) -> Analysis {
+    let mut eval_table = EvalTable::default();
+    let (value, per_move) = eval::eval_with_moves_parallel_cached(pos, &mut eval_table, threads);
+    let mut nimstring_table = NimstringTable::default();
+    let (nimstring_value, nimstring_per_move) =
+        nimstring::calc_value_with_moves_parallel_cached(pos, &mut nimstring_table, threads);
+
+    let mut moves: Vec<MoveAnalysis> = per_move.iter().map(|(&m, &value)| {
+        let nimstring_value = nimstring_per_move.iter()
+            .find(|&(&n, _)| pos.moves_equivalent(m, n)).map(|(_, &v)| v);
+        MoveAnalysis{m: m, value: value, nimstring_value: nimstring_value}
+    }).collect();
+    moves.sort_by_key(|a| (a.m.part, a.m.m.x, a.m.m.y, a.m.m.side));
+    // Of several equally good moves, the first listed is taken as best, as evalall
+    // lists them, which need not be the one eval would choose
+    let best_move = moves.iter().find(|a| a.value == value).map(|a| a.m);
+    Analysis{
+        position: pos.clone(),
+        value: value,
+        best_move: best_move,
+        nimstring_value: nimstring_value,
+        moves: moves,
+    }
+}
+
+// Analyse the position in a game file, giving the result as a JSON document
+pub fn analyse_file(filename: &str, threads: usize) -> Result<String, Error> {
+    let f = File::open(filename).map_err(
+        |e| Error::Io(format!("Could not open file [{}]: {}", filename, e)))?;
+    let pos = cli::parse_position(BufReader::new(f))?;
+    serde_json::to_string_pretty(&analyse(&pos, threads)).map_err(|e| Error::Io(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::*;
+    use crate::examples::*;
+    use crate::game::{SimplePosition, Move, Side, MoveOutcome};
+    use serde_json::{json, to_value};
+
+    #[test]
+    fn analysis() {
+        let pos = CompoundPosition::new(vec!(make_chain(2)));
+        let expected = json!({
+            "position": {"parts": [{"width": 2, "height": 1, "lines": [
+                {"x": 0, "y": 0, "side": "Top"}, {"x": 1, "y": 0, "side": "Top"},
+                {"x": 0, "y": 0, "side": "Bottom"}, {"x": 1, "y": 0, "side": "Bottom"}]}]},
+            "value": -2,
+            "best_move": {"part": 0, "m": {"x": 0, "y": 0, "side": "Left"}},
+            "nimstring_value": {"Nimber": 1},
+            "moves": [
+                {"move": {"part": 0, "m": {"x": 0, "y": 0, "side": "Left"}}, "value": -2, "nimstring_value": {"Loony": {"component": 0, "controlled_value": -2}}},
+                {"move": {"part": 0, "m": {"x": 0, "y": 0, "side": "Right"}}, "value": -2, "nimstring_value": {"Nimber": 0}},
+                {"move": {"part": 0, "m": {"x": 1, "y": 0, "side": "Right"}}, "value": -2, "nimstring_value": {"Loony": {"component": 0, "controlled_value": -2}}},
+            ],
+        });
+        assert_eq!(expected, to_value(analyse(&pos, 1)).unwrap());
+        assert_eq!(to_value(analyse(&pos, 1)).unwrap(), to_value(analyse(&pos, 2)).unwrap());
+    }
+
+    #[test]
+    fn round_trips() {
+        for simple in vec!(SimplePosition::new_game(3, 3), ex3p1(), p50(), double_loop(3), SimplePosition::new_end_game(2, 1)) {
+            let text = serde_json::to_string(&simple).unwrap();
+            let parsed: SimplePosition = serde_json::from_str(&text).unwrap();
+            assert_eq!(true, simple.eq(&parsed), "{}", text);
+            assert_eq!(simple.zhash(), parsed.zhash(), "{}", text);
+        }
+        for compound in vec!(one_long_multi_three(3, 4), CompoundPosition::from_single(ex3p1())) {
+            let text = serde_json::to_string(&compound).unwrap();
+            let parsed: CompoundPosition = serde_json::from_str(&text).unwrap();
+            assert_eq!(true, compound.eq(&parsed), "{}", text);
+            assert_eq!(compound.zhash(), parsed.zhash(), "{}", text);
+        }
+
+        let m = CPosMove::new(2, 1, 0, Side::Right);
+        assert_eq!(m, serde_json::from_str(&serde_json::to_string(&m).unwrap()).unwrap());
+        let m = Move::new(1, 2, Side::Bottom);
+        assert_eq!(m, serde_json::from_str(&serde_json::to_string(&m).unwrap()).unwrap());
+        let outcome = MoveOutcome{coins_captured: 2, end_of_turn: false, end_of_game: true};
+        assert_eq!(outcome, serde_json::from_str(&serde_json::to_string(&outcome).unwrap()).unwrap());
+        for v in vec!(Value::Nimber(0), Value::Nimber(3), Value::LOONY, Value::Loony{component: Some(2), controlled_value: Some(-1)}) {
+            assert_eq!(v, serde_json::from_str(&serde_json::to_string(&v).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let parse = |text: &str| serde_json::from_str::<SimplePosition>(text).map_err(|e| e.to_string());
+        assert_eq!(Err("Not a legal move: (0, 0) Top".to_string()),
+                   parse(r#"{"width": 1, "height": 1, "lines": [{"x": 0, "y": 0, "side": "Top"}, {"x": 0, "y": 0, "side": "Top"}]}"#).map(|_| ()));
+        assert_eq!(Err("Not a legal move: (1, 0) Left".to_string()),
+                   parse(r#"{"width": 1, "height": 1, "lines": [{"x": 1, "y": 0, "side": "Left"}]}"#).map(|_| ()));
+        assert_eq!(true, parse(r#"{"width": 64, "height": 1, "lines": []}"#).is_err());
+    }
+}
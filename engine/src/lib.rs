@@ -17,8 +17,23 @@
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
 pub mod game;
+pub mod error;
 pub mod nimstring;
 pub mod cli;
 pub mod splitter;
+pub mod fragment;
+pub mod nimdb;
 pub mod examples;
 pub mod eval;
+pub mod sandc;
+pub mod structure;
+pub mod endgame;
+pub mod parity;
+pub mod ttable;
+pub mod protocol;
+pub mod svg;
+pub mod batch;
+#[cfg(feature = "serde")]
+pub mod json;
+
+pub use error::Error;
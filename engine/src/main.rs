@@ -16,29 +16,148 @@
     You should have received a copy of the GNU Affero General Public License
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
-use dabengine::cli;
+use dabengine::{batch, cli, nimdb, protocol, Error};
+use dabengine::fragment::CanonicalFragment;
+use dabengine::nimdb::NimstringDb;
 use std::env;
+use std::io;
+use std::path::Path;
 use std::process;
 
 fn usage(name: &str) {
     println!("Usage:");
     println!("{} x y - start a new game of width x, height y", name);
     println!("{} cmd_file - read commands from cmd_file and start CLI from there", name);
+    println!("{} play x y [--engine-side a|b] - play a game of width x, height y against the engine (default side b)", name);
+    println!("{} batch [--csv] path... - evaluate the positions in files, or the .pos files in directories, printing a table or CSV", name);
+    println!("{} json file - print the analysis of the position in a game file as JSON", name);
+    println!("{} nimdb build n db_file - add the Nimstring values of all fragments of up to n coins to a database", name);
+    println!("{} nimdb query db_file fragment|file - look up the values of a fragment, or of the fragments of the position in a game file", name);
+    println!("{} protocol - communicate with another program using the engine protocol on stdin/stdout", name);
+}
+
+fn parse_dimension(arg: &str) -> usize {
+    arg.parse::<usize>().unwrap_or_else(|e| {
+        eprintln!("Could not parse dimension [{}]: {}", arg, e);
+        process::exit(1);
+    })
+}
+
+fn parse_side(arg: &str) -> usize {
+    match arg.to_lowercase().as_str() {
+        "a" => 0,
+        "b" => 1,
+        _ => {
+            eprintln!("Engine side must be a or b, not [{}]", arg);
+            process::exit(1);
+        },
+    }
+}
+
+fn threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn run_batch(args: &[String]) -> Result<(), Error> {
+    let (csv, paths) = match args {
+        [flag, paths @ ..] if flag == "--csv" => (true, paths),
+        paths => (false, paths),
+    };
+    let failures = batch::run(paths, csv, threads(), &mut io::stdout())?;
+    if failures > 0 {
+        eprintln!("{} file(s) could not be analysed", failures);
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_nimdb(name: &str, args: &[String]) -> Result<(), Error> {
+    match args {
+        [command, max_coins, filename] if command == "build" => {
+            let max_coins = max_coins.parse::<usize>().unwrap_or_else(|e| {
+                eprintln!("Could not parse number of coins [{}]: {}", max_coins, e);
+                process::exit(1);
+            });
+            let mut db = if Path::new(filename).exists() { NimstringDb::load(filename)? } else { NimstringDb::new() };
+            let added = db.build(max_coins);
+            db.save(filename)?;
+            println!("Added {} fragments, making {} in total", added, db.len());
+        },
+        [command, filename, query] if command == "query" => {
+            let db = NimstringDb::load(filename)?;
+            let fragments = if Path::new(query).is_file() {
+                nimdb::position_fragments(query)?
+            } else {
+                vec!(query.parse::<CanonicalFragment>()?)
+            };
+            for fragment in fragments {
+                match db.get(&fragment) {
+                    Some(value) => println!("{} {}", fragment, value),
+                    None => println!("{} not in database", fragment),
+                }
+            }
+        },
+        _ => {
+            usage(name);
+            process::exit(1);
+        },
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn print_json(filename: &str) -> Result<(), Error> {
+    dabengine::json::analyse_file(filename, threads()).map(|json| println!("{}", json))
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json(_filename: &str) -> Result<(), Error> {
+    eprintln!("JSON output requires the engine to be built with the serde feature");
+    process::exit(1);
 }
 
 fn main() {
     let args: Vec<_> = env::args().collect();
-    if args.len() == 3 {
-        let width = args[1].parse::<usize>().unwrap();
-        let height = args[2].parse::<usize>().unwrap();
-        cli::main_loop_start(width, height);
+    let result = if args.len() >= 4 && args[1] == "play" {
+        let width = parse_dimension(&args[2]);
+        let height = parse_dimension(&args[3]);
+        let engine_side = match &args[4..] {
+            [] => 1,
+            [flag, side] if flag == "--engine-side" => parse_side(side),
+            _ => {
+                usage(&args[0]);
+                process::exit(1);
+            },
+        };
+        cli::play_start(width, height, engine_side)
+    }
+    else if args.len() == 2 && args[1] == "protocol" {
+        protocol::run(io::stdin().lock(), io::stdout())
+    }
+    else if args.len() >= 3 && args[1] == "batch" {
+        run_batch(&args[2..])
+    }
+    else if args.len() >= 2 && args[1] == "nimdb" {
+        run_nimdb(&args[0], &args[2..])
+    }
+    else if args.len() == 3 && args[1] == "json" {
+        print_json(&args[2])
+    }
+    else if args.len() == 3 {
+        let width = parse_dimension(&args[1]);
+        let height = parse_dimension(&args[2]);
+        cli::main_loop_start(width, height)
     }
     else if args.len() == 2 {
         let filename = &args[1];
-        cli::main_loop_file(filename);
+        cli::main_loop_file(filename)
     }
     else {
         usage(&args[0]);
         process::exit(1);
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
     }
 }
@@ -0,0 +1,390 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::cli;
+use crate::error::Error;
+use crate::fragment::CanonicalFragment;
+use crate::game::{Move, Position, SimplePosition, Side};
+use crate::nimstring::{self, NimstringPosition, NimstringTable, Value, ValueCache};
+use crate::splitter::SplittablePosition;
+use crate::ttable::Cache;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+// Database of the Nimstring values of fragments, keyed by their canonical forms, which
+// can be saved to a file and consulted and extended while calculating values.
+//
+// The file is a line of text for each fragment, giving its text form and its value,
+// between a header naming the format and its version and a trailer giving the number
+// of fragments and a checksum of their lines. Files which are truncated, have been
+// changed, or are of another version are rejected.
+
+const FORMAT_NAME: &str = "dabengine-nimstring-db";
+
+// Version of the file format, to be increased whenever it changes or the values
+// stored in it might
+pub const VERSION: usize = 1;
+
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub struct NimstringDb {
+    values: BTreeMap<CanonicalFragment, Value>,
+}
+
+// 64-bit FNV-1a hash, which detects accidental changes to the file
+fn checksum(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+const CHECKSUM_START: u64 = 0xcbf29ce484222325;
+
+impl NimstringDb {
+    pub fn new() -> NimstringDb {
+        NimstringDb::default()
+    }
+
+    pub fn len(self: &NimstringDb) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(self: &NimstringDb) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get(self: &NimstringDb, fragment: &CanonicalFragment) -> Option<Value> {
+        self.values.get(fragment).copied()
+    }
+
+    // Record the value of a fragment. Nothing is kept about where any looniness comes from,
+    // as that depends on the position the fragment is found in.
+    pub fn insert(self: &mut NimstringDb, fragment: CanonicalFragment, value: Value) {
+        let value = if value.is_loony() { Value::LOONY } else { value };
+        self.values.insert(fragment, value);
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<NimstringDb, Error> {
+        let mut lines = reader.lines().enumerate();
+        let (_, header) = lines.next().ok_or_else(|| Error::Database("Database file is empty".to_string()))?;
+        let header = header.map_err(|e| Error::Io(format!("Could not read database: {}", e)))?;
+        let version = match header.split_once(' ') {
+            Some((name, version)) if name == FORMAT_NAME => version.parse::<usize>().ok(),
+            _ => None,
+        }.ok_or_else(|| Error::Database("Not a Nimstring value database".to_string()))?;
+        if version != VERSION {
+            return Err(Error::Database(format!(
+                "Database is of version {}, but only version {} is supported", version, VERSION)));
+        }
+
+        let mut result = NimstringDb::new();
+        let mut hash = CHECKSUM_START;
+        for (i, line) in lines.by_ref() {
+            let line = line.map_err(|e| Error::Io(format!("Could not read database: {}", e)))?;
+            let corrupt = |message: String| Error::Database(format!("Database is corrupt at line {}: {}", i + 1, message));
+            if let Some(trailer) = line.strip_prefix("end ") {
+                let expected = format!("{} {:016x}", result.len(), hash);
+                if trailer != expected {
+                    return Err(corrupt(format!("expected trailer [end {}]", expected)));
+                }
+                return match lines.next() {
+                    None => Ok(result),
+                    Some((i, _)) => Err(Error::Database(format!("Database is corrupt at line {}: text after trailer", i + 1))),
+                };
+            }
+            let (fragment, value) = line.split_once(' ').ok_or_else(|| corrupt("expected fragment and value".to_string()))?;
+            let fragment = fragment.parse::<CanonicalFragment>().map_err(|e| corrupt(e.to_string()))?;
+            let value = value.parse::<Value>().map_err(corrupt)?;
+            if result.values.insert(fragment, value).is_some() {
+                return Err(corrupt("duplicate fragment".to_string()));
+            }
+            hash = checksum(checksum(hash, line.as_bytes()), b"\n");
+        }
+        Err(Error::Database("Database is truncated".to_string()))
+    }
+
+    pub fn write<W: Write>(self: &NimstringDb, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{} {}", FORMAT_NAME, VERSION)?;
+        let mut hash = CHECKSUM_START;
+        for (fragment, value) in &self.values {
+            let line = format!("{} {}\n", fragment, value);
+            hash = checksum(hash, line.as_bytes());
+            writer.write_all(line.as_bytes())?;
+        }
+        writeln!(writer, "end {} {:016x}", self.len(), hash)
+    }
+
+    pub fn load(filename: &str) -> Result<NimstringDb, Error> {
+        let f = File::open(filename).map_err(
+            |e| Error::Io(format!("Could not open file [{}]: {}", filename, e)))?;
+        NimstringDb::read(BufReader::new(f))
+    }
+
+    pub fn save(self: &NimstringDb, filename: &str) -> Result<(), Error> {
+        let io_error = |e: io::Error| Error::Io(format!("Could not write file [{}]: {}", filename, e));
+        let mut f = io::BufWriter::new(File::create(filename).map_err(io_error)?);
+        self.write(&mut f).and_then(|_| f.flush()).map_err(io_error)
+    }
+
+    // Add every connected fragment with up to the given number of coins which is not
+    // already in the database, returning how many fragments were added
+    pub fn build(self: &mut NimstringDb, max_coins: usize) -> usize {
+        let before = self.len();
+        let mut table = NimstringTable::default();
+        for fragment in fragments(max_coins) {
+            if self.get(&fragment).is_some() {
+                continue;
+            }
+            let pos = fragment.to_position();
+            let part_values = nimstring::calc_part_values_cached(&pos, &mut DbTable{table: &mut table, db: self});
+            let value = part_values.into_iter().fold(Value::Nimber(0), |sum, v| sum + v);
+            self.insert(fragment, value);
+        }
+        self.len() - before
+    }
+}
+
+// A table of Nimstring values which also consults the database for the values of fragments,
+// and adds the value of each fragment it calculates to the database
+pub struct DbTable<'a> {
+    pub table: &'a mut NimstringTable,
+    pub db: &'a mut NimstringDb,
+}
+
+impl Cache<Value> for DbTable<'_> {
+    fn get(&mut self, key: usize) -> Option<Value> {
+        self.table.get(key)
+    }
+
+    fn insert(&mut self, key: usize, depth: usize, value: Value) {
+        self.table.insert(key, depth, value)
+    }
+}
+
+impl ValueCache for DbTable<'_> {
+    fn get_fragment(&mut self, fragment: &CanonicalFragment) -> Option<Value> {
+        self.db.get(fragment)
+    }
+
+    fn insert_fragment(&mut self, fragment: &CanonicalFragment, value: Value) {
+        self.db.insert(fragment.clone(), value)
+    }
+}
+
+// The cells of every fixed polyomino with up to the given number of cells,
+// each placed against the top and left edges
+fn polyominoes(max_cells: usize) -> Vec<Vec<(usize, usize)>> {
+    let mut result = Vec::new();
+    let mut current: BTreeSet<Vec<(usize, usize)>> = BTreeSet::new();
+    for size in 1..=max_cells {
+        current = if size == 1 {
+            [vec!((0, 0))].into_iter().collect()
+        } else {
+            let mut larger = BTreeSet::new();
+            for cells in &current {
+                for &(x, y) in cells {
+                    let neighbours = [(x as isize - 1, y as isize), (x as isize + 1, y as isize),
+                                      (x as isize, y as isize - 1), (x as isize, y as isize + 1)];
+                    for (nx, ny) in neighbours {
+                        let mut grown: Vec<(isize, isize)> = cells.iter().map(|&(x, y)| (x as isize, y as isize)).collect();
+                        if grown.contains(&(nx, ny)) {
+                            continue;
+                        }
+                        grown.push((nx, ny));
+                        let (min_x, min_y) = (nx.min(0), ny.min(0));
+                        let mut grown: Vec<(usize, usize)> = grown.into_iter()
+                            .map(|(x, y)| ((x - min_x) as usize, (y - min_y) as usize)).collect();
+                        grown.sort();
+                        larger.insert(grown);
+                    }
+                }
+            }
+            larger
+        };
+        result.extend(current.iter().cloned());
+    }
+    result
+}
+
+// Add the fragments whose coins fill the given cells to a set. Each has a connected
+// set of strings between the coins, and any number of ground links on each coin up to
+// the number of its sides at the edge of the board.
+fn add_shape_fragments(cells: &[(usize, usize)], result: &mut BTreeSet<CanonicalFragment>) {
+    let width = cells.iter().map(|&(x, _)| x).max().unwrap() + 1;
+    let height = cells.iter().map(|&(_, y)| y).max().unwrap() + 1;
+    let mut links = Vec::new();
+    for (i, &(x, y)) in cells.iter().enumerate() {
+        for (j, &(x2, y2)) in cells.iter().enumerate() {
+            if (x2, y2) == (x + 1, y) {
+                links.push((i, j, Move{x: x, y: y, side: Side::Right}));
+            } else if (x2, y2) == (x, y + 1) {
+                links.push((i, j, Move{x: x, y: y, side: Side::Bottom}));
+            }
+        }
+    }
+    let edge_sides = |x: usize, y: usize| (x == 0) as usize + (x + 1 == width) as usize + (y == 0) as usize + (y + 1 == height) as usize;
+
+    for mask in 0..(1usize << links.len()) {
+        let chosen: Vec<(usize, usize, Move)> = links.iter().enumerate()
+            .filter(|&(k, _)| mask & (1 << k) != 0).map(|(_, &l)| l).collect();
+        let mut component: Vec<usize> = (0..cells.len()).collect();
+        let mut degree = vec!(0; cells.len());
+        for &(i, j, _) in &chosen {
+            let (ci, cj) = (component[i], component[j]);
+            component.iter_mut().filter(|c| **c == cj).for_each(|c| *c = ci);
+            degree[i] += 1;
+            degree[j] += 1;
+        }
+        if component.iter().any(|&c| c != component[0]) {
+            continue;
+        }
+
+        let mut pos = SimplePosition::new_end_game(width, height);
+        for &(_, _, m) in &chosen {
+            pos.undo_move(m);
+        }
+        let limits: Vec<usize> = cells.iter().zip(&degree).map(|(&(x, y), &d)| edge_sides(x, y).min(4 - d)).collect();
+        let mut grounds = vec!(0; cells.len());
+        loop {
+            let mut ground_pos = pos.clone();
+            for (&(x, y), &g) in cells.iter().zip(&grounds) {
+                let edges: Vec<Side> = Side::ALL.into_iter().filter(|&side| pos.offset(x, y, side).is_none()).collect();
+                for &side in edges.iter().take(g) {
+                    ground_pos.undo_move(Move{x: x, y: y, side: side});
+                }
+            }
+            // A single coin needs a ground link to be in play
+            if cells.len() > 1 || grounds[0] > 0 {
+                result.insert(CanonicalFragment::new(&ground_pos));
+            }
+
+            // Move on to the next combination of ground links
+            let mut i = 0;
+            while i < grounds.len() && grounds[i] == limits[i] {
+                grounds[i] = 0;
+                i += 1;
+            }
+            if i == grounds.len() {
+                break;
+            }
+            grounds[i] += 1;
+        }
+    }
+}
+
+// Every connected fragment with between one and the given number of coins
+pub fn fragments(max_coins: usize) -> BTreeSet<CanonicalFragment> {
+    let mut result = BTreeSet::new();
+    for cells in polyominoes(max_coins) {
+        add_shape_fragments(&cells, &mut result);
+    }
+    result
+}
+
+// The fragments making up the position in a game file, in the order given by split
+pub fn position_fragments(filename: &str) -> Result<Vec<CanonicalFragment>, Error> {
+    let f = File::open(filename).map_err(
+        |e| Error::Io(format!("Could not open file [{}]: {}", filename, e)))?;
+    let pos = cli::parse_position(BufReader::new(f))?;
+    Ok(pos.split().iter().filter_map(|part| part.canonical_fragment()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nimdb::*;
+    use crate::examples::*;
+    use crate::game::CompoundPosition;
+
+    fn db_text(db: &NimstringDb) -> String {
+        let mut out = Vec::new();
+        db.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn fragment_counts() {
+        // A coin with one to four ground links, and two coins joined with up to three each
+        assert_eq!(4, fragments(1).len());
+        assert_eq!(14, fragments(2).len());
+        assert_eq!(true, fragments(4).contains(&CanonicalFragment::new(&make_chain(4))));
+        assert_eq!(true, fragments(4).iter().all(|f| f.coin_count() <= 4));
+    }
+
+    #[test]
+    fn built_values() {
+        let mut db = NimstringDb::new();
+        assert_eq!(fragments(4).len(), db.build(4));
+        assert_eq!(0, db.build(4));
+        for (fragment, &value) in &db.values {
+            let (expected, _) = nimstring::calc_value_with_moves(&fragment.to_position());
+            let expected = if expected.is_loony() { Value::LOONY } else { expected };
+            assert_eq!(expected, value, "{}", fragment);
+        }
+        assert_eq!(Some(Value::Nimber(0)), db.get(&CanonicalFragment::new(&make_chain(3))));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut db = NimstringDb::new();
+        db.build(3);
+        let text = db_text(&db);
+        assert_eq!(true, text.starts_with("dabengine-nimstring-db 1\n"));
+        assert_eq!(true, text.contains("\n1x1:1 *0\n"));
+        assert_eq!(db, NimstringDb::read(text.as_bytes()).unwrap());
+        assert_eq!(NimstringDb::new(), NimstringDb::read(db_text(&NimstringDb::new()).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn bad_files_rejected() {
+        let mut db = NimstringDb::new();
+        db.build(2);
+        let text = db_text(&db);
+        let rejected = |text: &str| match NimstringDb::read(text.as_bytes()) {
+            Err(Error::Database(message)) => message,
+            other => panic!("Unexpected result {:?}", other),
+        };
+        assert_eq!(true, rejected("").contains("empty"));
+        assert_eq!(true, rejected("3 3\n0 0 t\n").contains("Not a Nimstring value database"));
+        assert_eq!(true, rejected(&text.replacen("db 1", "db 2", 1)).contains("version 2"));
+        assert_eq!(true, rejected(&text[..text.find("end").unwrap()]).contains("truncated"));
+        assert_eq!(true, rejected(&text.replacen("1x1:1 *0", "1x1:1 *1", 1)).contains("expected trailer"));
+        assert_eq!(true, rejected(&text.replacen("1x1:1 *0", "1x1:1 0", 1)).contains("line 2"));
+        assert_eq!(true, rejected(&text.replacen("1x1:1", "1x1:5", 1)).contains("too many strings"));
+        assert_eq!(true, rejected(&text.replacen("1x1:1 *0", "1x1:1 *0\n1x1:1 *0", 1)).contains("duplicate"));
+        assert_eq!(true, rejected(&format!("{}1x1:1 *0\n", text)).contains("after trailer"));
+    }
+
+    #[test]
+    fn consulted_and_extended() {
+        // Values in the database are used in preference to searching
+        let mut db = NimstringDb::new();
+        db.insert(CanonicalFragment::new(&p50_top()), Value::Nimber(7));
+        let mut table = NimstringTable::default();
+        let part_values = nimstring::calc_part_values_cached(
+            &CompoundPosition::new(vec!(p50_top(), p50_bottomleft())), &mut DbTable{table: &mut table, db: &mut db});
+        assert_eq!(vec!(Value::Nimber(7), Value::Nimber(4)), part_values);
+
+        // Values calculated are added to it
+        assert_eq!(Some(Value::Nimber(4)), db.get(&CanonicalFragment::new(&p50_bottomleft())));
+        let mut db = NimstringDb::new();
+        let mut table = NimstringTable::default();
+        nimstring::calc_value_with_moves_cached(&p50(), &mut DbTable{table: &mut table, db: &mut db});
+        assert_eq!(Some(Value::Nimber(2)), db.get(&CanonicalFragment::new(&p50_bottomright())));
+    }
+}
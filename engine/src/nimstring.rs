@@ -16,29 +16,64 @@
     You should have received a copy of the GNU Affero General Public License
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
+use crate::endgame;
+use crate::fragment::CanonicalFragment;
 use crate::game::{Position, SimplePosition, CompoundPosition, Side, Move, CPosMove};
 use crate::splitter::SplittablePosition;
+use crate::structure::{self, StructureKind};
+use crate::ttable::{Cache, ShardedTable, TranspositionTable, SHARDS_PER_THREAD};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::hash::Hash;
+use std::iter;
+use std::mem;
 use std::ops;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 #[derive(Clone)]
 #[derive(Copy)]
 #[derive(PartialEq)]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub enum Value {
     Nimber(usize),
-    Loony
+    // Where known, a loony value records which component (by its index in the order
+    // given by split) made the position loony, and the controlled value of that component
+    Loony{component: Option<usize>, controlled_value: Option<isize>},
+}
+
+impl Value {
+    // A loony value with nothing known about where the looniness comes from
+    pub const LOONY: Value = Value::Loony{component: None, controlled_value: None};
+
+    pub fn is_loony(self: &Value) -> bool {
+        matches!(self, Value::Loony{..})
+    }
+
+    // This value as the value of the component with the given index in a larger position
+    fn in_component(self: Value, index: usize) -> Value {
+        match self {
+            Value::Loony{component: None, controlled_value} =>
+                Value::Loony{component: Some(index), controlled_value: controlled_value},
+            _ => self,
+        }
+    }
 }
 
 impl ops::Add for Value {
     type Output = Value;
 
+    // L + *n = L and L + L = L, keeping what is known about the first loony operand
     fn add(self: Value, other: Value) -> Value {
         match (self, other) {
-            (Value::Loony, _) => Value::Loony,
-            (_, Value::Loony) => Value::Loony,
+            (Value::Loony{..}, _) => self,
+            (_, Value::Loony{..}) => other,
             (Value::Nimber(x), Value::Nimber(y)) => Value::Nimber(x ^ y),
         }
     }
@@ -48,41 +83,124 @@ impl fmt::Display for Value {
     fn fmt(self: &Value, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             &Value::Nimber(v) => write!(f, "*{}", v),
-            &Value::Loony => write!(f, "L"),
+            &Value::Loony{..} => write!(f, "L"),
         }
     }
 }
 
+// Read a value in its display form, so with nothing known about where any looniness comes from
+impl FromStr for Value {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Value, String> {
+        if text == "L" {
+            return Ok(Value::LOONY);
+        }
+        text.strip_prefix('*').and_then(|n| n.parse::<usize>().ok()).map(Value::Nimber)
+            .ok_or_else(|| format!("Invalid Nimstring value [{}]", text))
+    }
+}
+
 pub trait NimstringPosition<M>: SplittablePosition<M> {
     // Indicate whether a given position is loony
     fn is_loony(&self) -> bool;
+
+    // The controlled value of a position consisting of a single loony component,
+    // i.e. the net number of coins the player to move gets from it by keeping control
+    // (declining the last coins of it), if this can be determined
+    fn controlled_value(&self) -> Option<isize> {
+        None
+    }
+
+    // The value of a non-loony position in which nothing can be captured,
+    // if it is made of patterns whose values are known without searching them
+    fn known_value(&self) -> Option<Value> {
+        None
+    }
+
+    // Canonical form shared by every fragment known to have the same value as this one,
+    // if the position has one
+    fn canonical_fragment(&self) -> Option<CanonicalFragment> {
+        None
+    }
+
+    // Reduce a list of moves to those which need to be tried to find the value of
+    // a non-loony position in which nothing can be captured. Loony moves are left out,
+    // as they make no difference to the mex.
+    fn retain_nimstring_moves(&mut self, moves: &mut Vec<M>)
+    where M: Copy {
+        moves.retain(|&m| {
+            self.make_move(m);
+            let loony = self.is_loony();
+            self.undo_move(m);
+            !loony
+        });
+    }
 }
 
 impl NimstringPosition<Move> for SimplePosition {
     fn is_loony(self: &SimplePosition) -> bool {
+        self.capturable_coins().into_iter().any(|(x, y)| offers_loony(self, x, y, None))
+    }
+
+    fn controlled_value(self: &SimplePosition) -> Option<isize> {
+        // On its own the component is the last one left, which is taken in full.
+        // Keeping control instead gives the opponent the coins declined: the last two
+        // of an opened chain, or the last four of an opened loop.
+        let declined = match structure::classify(self).kind {
+            StructureKind::Loop => 4,
+            _ => 2,
+        };
+        endgame::controlled_value(self).map(|cv| cv - 2 * declined)
+    }
+
+    fn known_value(self: &SimplePosition) -> Option<Value> {
+        // Without joints, a position is made up of independent chains and loops.
+        // Every move in a loop or a long chain is loony, while any move in a short
+        // chain leads to its capture, so only the number of short chains matters.
+        let mut short_chains = 0;
+        let mut two_chain_ends = 0;
         for x in 0..self.width() {
             for y in 0..self.height() {
-                if self.valency(x, y) != 1 {
-                    continue
-                }
-                if let Some((neighbour_x, neighbour_y, side)) = connected_coin(self, x, y, Side::all()) {
-                    if self.valency(neighbour_x, neighbour_y) != 2 {
-                        continue
-                    }
-                    // We have found a capturable coin attached to a coin of valency 2 (o-o-?).
-                    // This means the position is loony unless there is a valency-1 coin
-                    // on the other side (o-o-o).
-                    let far_sides = Side::all_except(side.opposite());
-                    if let Some((far_x, far_y, _)) = connected_coin(self, neighbour_x, neighbour_y, far_sides) {
-                        if self.valency(far_x, far_y) == 1 {
-                            continue;
+                match (self.valency(x, y), ground_links(self, x, y)) {
+                    (0, _) => continue,
+                    (v, _) if v != 2 => return None,
+                    (_, 2) => short_chains += 1,
+                    (_, 1) => {
+                        let (nx, ny, _) = connected_coin(self, x, y, None).unwrap();
+                        if ground_links(self, nx, ny) == 1 {
+                            two_chain_ends += 1;
                         }
-                    }
-                    return true;
+                    },
+                    _ => (),
                 }
             }
         }
-        false
+        Some(Value::Nimber((short_chains + two_chain_ends / 2) % 2))
+    }
+
+    fn canonical_fragment(self: &SimplePosition) -> Option<CanonicalFragment> {
+        Some(CanonicalFragment::new(self))
+    }
+
+    fn retain_nimstring_moves(self: &mut SimplePosition, moves: &mut Vec<Move>) {
+        // Once the coins it exposes are captured, cutting any string of a chain
+        // (a run of coins of valency 2) removes the whole chain, so a non-loony
+        // move in a chain is equivalent to any other
+        let mut chains_tried = Vec::new();
+        moves.retain(|&m| {
+            if would_offer_loony(self, m) {
+                return false;
+            }
+            match short_chain_key(self, m) {
+                Some(chain) if chains_tried.contains(&chain) => false,
+                Some(chain) => {
+                    chains_tried.push(chain);
+                    true
+                },
+                None => true,
+            }
+        });
     }
 }
 
@@ -98,10 +216,39 @@ impl NimstringPosition<CPosMove> for CompoundPosition {
     }
 }
 
-// If there is a coin connected to (x,y) on one of the given sides, return one such, else None.
-fn connected_coin(pos: &SimplePosition, x: usize, y: usize, sides: Vec<Side>) -> Option<(usize, usize, Side)> {
-    for &s in &sides {
-        if pos.is_legal_move(Move{x: x, y: y, side: s}) {
+// Indicate whether a capturable coin at (x, y), other than on the given side,
+// makes the position loony
+fn offers_loony(pos: &SimplePosition, x: usize, y: usize, except: Option<Side>) -> bool {
+    if let Some((neighbour_x, neighbour_y, side)) = connected_coin(pos, x, y, except) {
+        if pos.valency(neighbour_x, neighbour_y) != 2 {
+            return false;
+        }
+        // We have found a capturable coin attached to a coin of valency 2 (o-o-?).
+        // This means the position is loony unless there is a valency-1 coin
+        // on the other side (o-o-o).
+        if let Some((far_x, far_y, _)) = connected_coin(pos, neighbour_x, neighbour_y, Some(side.opposite())) {
+            if pos.valency(far_x, far_y) == 1 {
+                return false;
+            }
+        }
+        return true;
+    }
+    false
+}
+
+// As would_be_loony, for a position in which nothing can be captured. Then only a coin
+// at either end of the string cut can be offered, which can be checked without making
+// the move (and since the board is bipartite, the move changes no other valency involved).
+fn would_offer_loony(pos: &SimplePosition, m: Move) -> bool {
+    let far_end = pos.offset(m.x, m.y, m.side).map(|(x, y)| (x, y, m.side.opposite()));
+    [Some((m.x, m.y, m.side)), far_end].iter().flatten()
+        .any(|&(x, y, cut)| pos.valency(x, y) == 2 && offers_loony(pos, x, y, Some(cut)))
+}
+
+// If there is a coin connected to (x,y) on a side other than the given one, return one such, else None.
+fn connected_coin(pos: &SimplePosition, x: usize, y: usize, except: Option<Side>) -> Option<(usize, usize, Side)> {
+    for s in Side::ALL {
+        if Some(s) != except && pos.is_legal_move(Move{x: x, y: y, side: s}) {
             if let Some((nx, ny)) = pos.offset(x, y, s) {
                 return Some((nx, ny, s));
             }
@@ -110,6 +257,28 @@ fn connected_coin(pos: &SimplePosition, x: usize, y: usize, sides: Vec<Side>) ->
     None
 }
 
+// Number of strings attaching (x, y) to the ground
+fn ground_links(pos: &SimplePosition, x: usize, y: usize) -> usize {
+    Side::ALL.iter()
+        .filter(|&&side| pos.is_legal_move(Move{x: x, y: y, side: side}) && pos.offset(x, y, side).is_none())
+        .count()
+}
+
+// Identify the chain (run of coins of valency 2) which a string belongs to, if any,
+// by the lowest coordinates of its coins. Only chains of at most two coins are
+// identified correctly, but any move in a longer chain is loony anyway.
+fn short_chain_key(pos: &SimplePosition, m: Move) -> Option<(usize, usize)> {
+    let with_neighbours = |&(x, y): &(usize, usize)| Side::ALL.iter()
+        .filter(move |&&side| pos.is_legal_move(Move{x: x, y: y, side: side}))
+        .filter_map(move |&side| pos.offset(x, y, side))
+        .chain(iter::once((x, y)));
+    [Some((m.x, m.y)), pos.offset(m.x, m.y, m.side)].iter().flatten()
+        .filter(|&&(x, y)| pos.valency(x, y) == 2)
+        .flat_map(with_neighbours)
+        .filter(|&(x, y)| pos.valency(x, y) == 2)
+        .min()
+}
+
 // Indicate whether a given move would result in a loony position
 // (regardless of whether the current position is loony).
 // Note that a move is loony iff it returns true here *and* it is not a capture.
@@ -134,42 +303,68 @@ fn mex(s: HashSet<usize>) -> usize {
     }
 }
 
-fn calc_value<M, P>(pos: &mut P, cache: &mut HashMap<usize, Value>) -> Value
-where M: Copy, P: NimstringPosition<M> {
-    // TODO: Optimise by iterating over a tighter set of moves than all legal moves
-    if let Some(&v) = cache.get(&pos.zhash()) {
+// Table of Nimstring values which can be shared between calls to calc_value_with_moves_cached
+pub type NimstringTable = TranspositionTable<Value>;
+
+// A cache of Nimstring values which can also keep the values of fragments under their
+// canonical forms, rather than only under keys, so that they can be stored elsewhere
+pub trait ValueCache: Cache<Value> {
+    fn get_fragment(&mut self, _fragment: &CanonicalFragment) -> Option<Value> {
+        None
+    }
+
+    fn insert_fragment(&mut self, _fragment: &CanonicalFragment, _value: Value) {}
+}
+
+impl ValueCache for NimstringTable {}
+
+impl ValueCache for &ShardedTable<Value> {}
+
+fn calc_value<M, P, C>(pos: &mut P, cache: &mut C) -> Value
+where M: Copy, P: NimstringPosition<M>, C: ValueCache {
+    // Reflections and rotations of a position all have the same value
+    let key = pos.canonical_zhash();
+    if let Some(v) = cache.get(key) {
         return v;
     }
     if pos.is_loony() {
-        cache.insert(pos.zhash(), Value::Loony);
-        return Value::Loony;
+        cache.insert(key, 0, Value::LOONY);
+        return Value::LOONY;
     }
 
-    let legal_moves = pos.legal_moves();
+    // Positions with more moves left are more expensive to recalculate
+    let mut legal_moves = pos.legal_moves();
+    let depth = legal_moves.len();
     for &m in &legal_moves {
         if pos.would_capture(m) > 0 {
             pos.make_move(m);
             let result = calc_value(pos, cache);
             pos.undo_move(m);
-            cache.insert(pos.zhash(), result);
+            cache.insert(key, depth, result);
             return result
         }
     }
 
+    if let Some(result) = pos.known_value() {
+        cache.insert(key, depth, result);
+        return result;
+    }
+
     // Try to split the position into independent parts which can be evaluated separately
     let parts = pos.split();
     if parts.len() > 1 {
         let mut result = Value::Nimber(0);
         for mut part in parts {
-            let part_value = calc_value(&mut part, cache);
+            let part_value = calc_fragment_value(&mut part, cache);
             result = result + part_value;
         }
-        cache.insert(pos.zhash(), result);
+        cache.insert(key, depth, result);
         return result;
     }
 
     let mut options = HashSet::new();
-    for &m in &legal_moves {
+    pos.retain_nimstring_moves(&mut legal_moves);
+    for m in legal_moves {
         pos.make_move(m);
         if let Value::Nimber(n) = calc_value(pos, cache) {
             options.insert(n);
@@ -177,26 +372,144 @@ where M: Copy, P: NimstringPosition<M> {
         pos.undo_move(m);
     }
     let result = Value::Nimber(mex(options));
-    cache.insert(pos.zhash(), result);
+    cache.insert(key, depth, result);
     result
 }
 
+// Value of a fragment produced by split, which is looked up by its canonical form
+// so that it is only calculated once wherever it appears
+fn calc_fragment_value<M, P, C>(part: &mut P, cache: &mut C) -> Value
+where M: Copy, P: NimstringPosition<M>, C: ValueCache {
+    // Try the cheaper ways first
+    let key = part.canonical_zhash();
+    if let Some(v) = cache.get(key) {
+        return v;
+    }
+    if let Some(v) = part.known_value() {
+        return v;
+    }
+    let depth = part.legal_move_count();
+    let fragment = part.canonical_fragment();
+    let fragment_key = fragment.as_ref().map_or(key, |f| f.key());
+    let stored = cache.get(fragment_key).or_else(|| fragment.as_ref().and_then(|f| cache.get_fragment(f)));
+    if let Some(v) = stored {
+        cache.insert(key, depth, v);
+        cache.insert(fragment_key, depth, v);
+        return v;
+    }
+    let result = calc_value(part, cache);
+    cache.insert(fragment_key, depth, result);
+    if let Some(f) = fragment {
+        cache.insert_fragment(&f, result);
+    }
+    result
+}
+
+// Value of one independent part of a position, along with its controlled value if it is loony
+fn calc_part_value<M, P, C>(part: &mut P, cache: &mut C) -> Value
+where M: Copy, P: NimstringPosition<M>, C: ValueCache {
+    match calc_fragment_value(part, cache) {
+        Value::Loony{component, ..} => Value::Loony{component: component, controlled_value: part.controlled_value()},
+        v => v,
+    }
+}
+
+// Sum the values of the parts of a position, recording the first part which makes it loony
+fn sum_parts(values: Vec<Value>) -> Value {
+    values.into_iter().enumerate().fold(Value::Nimber(0), |sum, (i, v)| sum + v.in_component(i))
+}
+
+// Calculate the Nimstring value of each independent part of a position, in the order
+// given by split, reusing and adding to the values found by earlier calls
+pub fn calc_part_values_cached<M, P, C>(pos: &P, cache: &mut C) -> Vec<Value>
+where P: NimstringPosition<M>, C: ValueCache {
+    pos.split().into_iter().map(|mut part| calc_part_value(&mut part, cache)).collect()
+}
+
 // Calculate the Nimstring value of a position, along with the values attained
-// by each of the legal moves.
+// by each of the legal moves. Loony values record where the looniness comes from,
+// for the position and after each move alike.
 pub fn calc_value_with_moves<M, P>(pos: &P) -> (Value, HashMap<M, Value>)
 where M: Hash + Eq + Copy, P: NimstringPosition<M> + Clone {
-    let mut cache = HashMap::new();
+    calc_value_with_moves_cached(pos, &mut NimstringTable::default())
+}
+
+// As calc_value_with_moves, but reusing and adding to the values found by earlier calls
+pub fn calc_value_with_moves_cached<M, P, C>(pos: &P, cache: &mut C) -> (Value, HashMap<M, Value>)
+where M: Hash + Eq + Copy, P: NimstringPosition<M> + Clone, C: ValueCache {
     let mut pos = pos.clone();
-    let val = calc_value(&mut pos, &mut cache);
+    let val = sum_parts(calc_part_values_cached(&pos, cache));
     let mut per_move = HashMap::new();
     for m in pos.legal_moves() {
         pos.make_move(m);
-        per_move.insert(m, calc_value(&mut pos, &mut cache));
+        per_move.insert(m, sum_parts(calc_part_values_cached(&pos, cache)));
         pos.undo_move(m);
     }
     (val, per_move)
 }
 
+// As calc_value_with_moves, but using several threads
+pub fn calc_value_with_moves_parallel<M, P>(pos: &P, threads: usize) -> (Value, HashMap<M, Value>)
+where M: Hash + Eq + Copy + Send + Sync, P: NimstringPosition<M> + Clone + Sync, P::Part: Send {
+    calc_value_with_moves_parallel_cached(pos, &mut NimstringTable::default(), threads)
+}
+
+// As calc_value_with_moves_cached, but using several threads which share the table:
+// first to value the independent parts of the position concurrently, then to value
+// the positions after each move concurrently.
+pub fn calc_value_with_moves_parallel_cached<M, P>(pos: &P, cache: &mut NimstringTable, threads: usize)
+                                                   -> (Value, HashMap<M, Value>)
+where M: Hash + Eq + Copy + Send + Sync, P: NimstringPosition<M> + Clone + Sync, P::Part: Send {
+    if threads <= 1 {
+        return calc_value_with_moves_cached(pos, cache);
+    }
+    let shared = ShardedTable::new(mem::take(cache), threads * SHARDS_PER_THREAD);
+
+    // The value of a position is the sum of the values of its parts
+    let parts = Mutex::new(pos.split().into_iter().enumerate().collect::<Vec<_>>());
+    let part_count = parts.lock().unwrap().len();
+    let part_values = Mutex::new(vec!(Value::Nimber(0); part_count));
+    thread::scope(|scope| {
+        for _ in 0..threads.min(part_count) {
+            scope.spawn(|| {
+                let mut table = &shared;
+                loop {
+                    let part = parts.lock().unwrap().pop();
+                    if let Some((i, mut part)) = part {
+                        let part_value = calc_part_value(&mut part, &mut table);
+                        part_values.lock().unwrap()[i] = part_value;
+                    } else {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    let moves = pos.legal_moves();
+    let next = AtomicUsize::new(0);
+    let per_move = Mutex::new(HashMap::new());
+    thread::scope(|scope| {
+        for _ in 0..threads.min(moves.len()) {
+            scope.spawn(|| {
+                let mut table = &shared;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= moves.len() {
+                        break;
+                    }
+                    let mut child = pos.clone();
+                    child.make_move(moves[i]);
+                    let value = sum_parts(calc_part_values_cached(&child, &mut table));
+                    per_move.lock().unwrap().insert(moves[i], value);
+                }
+            });
+        }
+    });
+    *cache = shared.into_table();
+    (sum_parts(part_values.into_inner().unwrap()), per_move.into_inner().unwrap())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::nimstring::*;
@@ -208,7 +521,11 @@ mod tests {
         assert_eq!("*0", format!("{}", Value::Nimber(0)));
         assert_eq!("*1", format!("{}", Value::Nimber(1)));
         assert_eq!("*2", format!("{}", Value::Nimber(2)));
-        assert_eq!("L", format!("{}", Value::Loony));
+        assert_eq!("L", format!("{}", Value::LOONY));
+        assert_eq!(Ok(Value::Nimber(12)), "*12".parse::<Value>());
+        assert_eq!(Ok(Value::LOONY), "L".parse::<Value>());
+        assert_eq!(true, "12".parse::<Value>().is_err());
+        assert_eq!(true, "*x".parse::<Value>().is_err());
     }
 
     #[test]
@@ -231,22 +548,29 @@ mod tests {
         assert_eq!(Value::Nimber(4), Value::Nimber(6) + Value::Nimber(2));
         assert_eq!(Value::Nimber(2), Value::Nimber(4) + Value::Nimber(6));
 
-        assert_eq!(Value::Loony, Value::Nimber(2) + Value::Loony);
-        assert_eq!(Value::Loony, Value::Loony + Value::Nimber(2));
+        assert_eq!(Value::LOONY, Value::Nimber(2) + Value::LOONY);
+        assert_eq!(Value::LOONY, Value::LOONY + Value::Nimber(2));
+
+        let first = Value::Loony{component: Some(0), controlled_value: Some(3)};
+        let second = Value::Loony{component: Some(1), controlled_value: None};
+        assert_eq!(first, first + second);
+        assert_eq!(second, second + first);
+        assert_eq!(second, Value::Nimber(1) + second);
+        assert_eq!(true, (Value::Nimber(1) + second).is_loony());
     }
 
     #[test]
     fn basic_values() {
         let mut pos = make_chain(3);
         assert!(!pos.is_loony());
-        let mut cache = HashMap::new();
+        let mut cache = NimstringTable::default();
         assert_eq!(Value::Nimber(0), calc_value(&mut pos, &mut cache));
         pos.make_move(Move{x: 0, y: 0, side: Side::Left});
         assert!(pos.is_loony());
-        assert_eq!(Value::Loony, calc_value(&mut pos, &mut cache));
+        assert_eq!(Value::LOONY, calc_value(&mut pos, &mut cache));
         pos.make_move(Move{x: 1, y: 0, side: Side::Left});
         assert!(pos.is_loony());
-        assert_eq!(Value::Loony, calc_value(&mut pos, &mut cache));
+        assert_eq!(Value::LOONY, calc_value(&mut pos, &mut cache));
         pos.make_move(Move{x: 2, y: 0, side: Side::Left});
         assert!(!pos.is_loony());
         assert_eq!(Value::Nimber(0), calc_value(&mut pos, &mut cache));
@@ -381,11 +705,132 @@ mod tests {
     fn compound_values() {
         let mut pos = CompoundPosition::new(vec!(make_chain(5), make_chain(5)));
         assert_eq!(false, pos.is_loony());
-        let (val, _per_move) = calc_value_with_moves(&pos);
+        let (val, per_move) = calc_value_with_moves(&pos);
         assert_eq!(Value::Nimber(0), val);
+        let opened = Value::Loony{component: Some(1), controlled_value: Some(1)};
+        assert_eq!(Some(&opened), per_move.get(&CPosMove::new(1, 0, 0, Side::Left)));
+        assert_eq!(per_move, calc_value_with_moves_parallel(&pos, 2).1);
         pos.make_move(CPosMove::new(1, 0, 0, Side::Left));
         assert_eq!(true, pos.is_loony());
         let (val, _per_move) = calc_value_with_moves(&pos);
-        assert_eq!(Value::Loony, val);
+        assert_eq!(true, val.is_loony());
+        assert_eq!(opened, val);
+    }
+
+    // Unpruned search over every legal move, to compare against
+    fn search(pos: &mut SimplePosition, cache: &mut HashMap<usize, Value>) -> Value {
+        let key = pos.canonical_zhash();
+        if let Some(&v) = cache.get(&key) {
+            return v;
+        }
+        let result = if pos.is_loony() {
+            Value::LOONY
+        } else if let Some(m) = pos.legal_moves().into_iter().find(|&m| pos.would_capture(m) > 0) {
+            pos.make_move(m);
+            let v = search(pos, cache);
+            pos.undo_move(m);
+            v
+        } else {
+            let mut options = HashSet::new();
+            for m in pos.legal_moves() {
+                pos.make_move(m);
+                if let Value::Nimber(n) = search(pos, cache) {
+                    options.insert(n);
+                }
+                pos.undo_move(m);
+            }
+            Value::Nimber(mex(options))
+        };
+        cache.insert(key, result);
+        result
+    }
+
+    #[test]
+    fn pruning_cross_check() {
+        let positions = vec!(p50_top(), p50_bottomleft(), p50_bottomright(), ex6p2(), make_chain(2),
+                             double_chain(2), double_loop(2), icelandic_game(2, 2), SimplePosition::new_game(2, 2),
+                             SimplePosition::new_game(3, 2), multi_chains(2, 3), multi_chains(3, 2));
+        for (i, pos) in positions.iter().enumerate() {
+            let mut pos = pos.clone();
+            let expected = search(&mut pos, &mut HashMap::new());
+            assert_eq!(expected, calc_value(&mut pos, &mut NimstringTable::default()), "Position {}", i);
+        }
+    }
+
+    #[test]
+    fn pruned_moves() {
+        // All moves in a long chain or a loop are loony, and any move in a short chain captures it
+        let mut pos = make_chain(3);
+        assert_eq!(Some(Value::Nimber(0)), pos.known_value());
+        let mut moves = pos.legal_moves();
+        pos.retain_nimstring_moves(&mut moves);
+        assert_eq!(0, moves.len());
+        assert_eq!(Some(Value::Nimber(1)), make_chain(1).known_value());
+        assert_eq!(Some(Value::Nimber(1)), make_chain(2).known_value());
+        assert_eq!(Some(Value::Nimber(0)), double_loop(2).known_value());
+        assert_eq!(Some(Value::Nimber(1)), multi_chains(2, 3).known_value());
+        assert_eq!(Some(Value::Nimber(0)), multi_chains(2, 4).known_value());
+        assert_eq!(Some(Value::Nimber(0)), multi_chains(4, 3).known_value());
+        assert_eq!(None, p50_top().known_value());
+
+        // Loony moves, and all but one move in each chain, are left out
+        let mut pos = p50_bottomright();
+        let mut moves = pos.legal_moves();
+        pos.retain_nimstring_moves(&mut moves);
+        assert_eq!(true, moves.len() < pos.legal_move_count());
+        for &m in &moves {
+            assert_eq!(false, would_be_loony(&mut pos, m));
+        }
+    }
+
+    #[test]
+    fn shared_fragments() {
+        // The values of the parts of p50 are shared with the parts on their own
+        let mut table = NimstringTable::default();
+        calc_value_with_moves_cached(&p50(), &mut table);
+        assert_eq!(Some(Value::Nimber(1)), table.get(CanonicalFragment::new(&p50_top()).key()));
+        assert_eq!(Some(Value::Nimber(4)), table.get(CanonicalFragment::new(&p50_bottomleft()).key()));
+        assert_eq!(Some(Value::Nimber(2)), table.get(CanonicalFragment::new(&p50_bottomright()).key()));
+        let part_values = calc_part_values_cached(&CompoundPosition::new(vec!(p50_bottomright(), p50_top())), &mut table);
+        assert_eq!(vec!(Value::Nimber(2), Value::Nimber(1)), part_values);
+    }
+
+    #[test]
+    fn loony_components() {
+        let mut pos = CompoundPosition::new(vec!(make_chain(3), double_loop(3), make_chain(4)));
+        pos.make_move(CPosMove::new(1, 0, 0, Side::Right));
+        pos.make_move(CPosMove::new(2, 0, 0, Side::Left));
+        let part_values = calc_part_values_cached(&pos, &mut NimstringTable::default());
+        assert_eq!(vec!(Value::Nimber(0), Value::Loony{component: None, controlled_value: Some(-2)},
+                        Value::Nimber(0), Value::Loony{component: None, controlled_value: Some(0)}),
+                   part_values);
+        let expected = Value::Loony{component: Some(1), controlled_value: Some(-2)};
+        assert_eq!(expected, calc_value_with_moves(&pos).0);
+        assert_eq!(expected, calc_value_with_moves_parallel(&pos, 3).0);
+    }
+
+    #[test]
+    fn simple_controlled_values() {
+        let mut chain = make_chain(5);
+        chain.make_move(Move{x: 0, y: 0, side: Side::Left});
+        assert_eq!(Some(1), chain.controlled_value());
+        let mut quad = double_loop(2).split().remove(0);
+        quad.make_move(Move{x: 0, y: 0, side: Side::Right});
+        assert_eq!(Some(-4), quad.controlled_value());
+        assert_eq!(None, SimplePosition::new_game(2, 2).controlled_value());
+    }
+
+    #[test]
+    fn parallel_values() {
+        let positions = vec!(p50(), ex6p2(), ex7p2(), make_chain(3), SimplePosition::new_game(2, 2));
+        for (i, pos) in positions.iter().enumerate() {
+            assert_eq!(calc_value_with_moves(pos), calc_value_with_moves_parallel(pos, 3), "Position {}", i);
+        }
+        let mut pos = CompoundPosition::new(vec!(p50_top(), p50_bottomleft(), p50_bottomright(), make_chain(4)));
+        let mut table = NimstringTable::default();
+        assert_eq!(calc_value_with_moves(&pos), calc_value_with_moves_parallel_cached(&pos, &mut table, 4));
+        assert!(table.len() > 0);
+        pos.make_move(CPosMove::new(3, 0, 0, Side::Left));
+        assert_eq!(calc_value_with_moves(&pos), calc_value_with_moves_parallel_cached(&pos, &mut table, 4));
     }
 }
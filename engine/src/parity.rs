@@ -0,0 +1,319 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::game::{Move, Position, Side, SimplePosition};
+use crate::nimstring::NimstringPosition;
+use crate::splitter::SplittablePosition;
+use crate::structure::{self, StructureKind};
+use std::fmt;
+
+// Advice based on the long chain rule (Theorem gamelength in the paper).
+// If s strings and c coins are left and D double-crosses are still to come,
+// there are 1 + s - c + D turns left, so the player to move gets the last turn,
+// and with it usually the game, if s - c + D is even. In a game which ends in
+// long chains and loops D normally has the parity of L + 1, where L is the number of
+// long chains, so the player to move wants s - c + L to be odd. On a fresh board
+// s - c = T - 1 for T dots, which gives the paper's rule that the first player
+// wants T + L to be even.
+//
+// Components which are not yet chains or loops are estimated by cutting them at
+// their joints (coins joined to three or more other coins): each arm of at least
+// three coins left between joints and the ground is expected to become a long chain.
+// A ring of coins with strings to the ground could still become either a loop or a
+// chain, so it is reported as undecided and does not count as a long chain.
+
+// Which player the long chain rule currently favours
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub enum Favoured {
+    PlayerToMove,
+    Opponent,
+}
+
+impl fmt::Display for Favoured {
+    fn fmt(self: &Favoured, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Favoured::PlayerToMove => write!(f, "the player to move"),
+            Favoured::Opponent => write!(f, "their opponent"),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub struct ParityAnalysis {
+    // Dots on the board, T in the paper
+    pub dots: usize,
+    pub strings: usize,
+    pub coins: usize,
+    // Components which are already long chains
+    pub long_chains: usize,
+    // Long chains expected to form from components which are not yet chains or loops
+    pub potential_long_chains: usize,
+    // Components which could still become either a loop or a long chain
+    pub undecided: usize,
+    // Quiet moves (neither captures nor loony) which change the parity of the expected
+    // number of long chains, and those which keep it, one from each equivalent group
+    pub flipping_moves: Vec<Move>,
+    pub keeping_moves: Vec<Move>,
+}
+
+impl ParityAnalysis {
+    // Number of long chains expected at the end of the game
+    pub fn expected_long_chains(self: &ParityAnalysis) -> usize {
+        self.long_chains + self.potential_long_chains
+    }
+
+    // Indicate whether the player to move wants an odd number of long chains
+    pub fn wants_odd(self: &ParityAnalysis) -> bool {
+        (self.strings + self.coins) % 2 == 0
+    }
+
+    // Indicate whether the first player wants an odd number of long chains, as a
+    // rule of thumb for the whole game: this is right if no double-crosses have happened
+    pub fn first_player_wants_odd(self: &ParityAnalysis) -> bool {
+        self.dots % 2 == 1
+    }
+
+    pub fn favoured(self: &ParityAnalysis) -> Favoured {
+        if (self.expected_long_chains() % 2 == 1) == self.wants_odd() {
+            Favoured::PlayerToMove
+        } else {
+            Favoured::Opponent
+        }
+    }
+
+    // Quiet moves which leave the rule favouring the player who makes them
+    pub fn recommended_moves(self: &ParityAnalysis) -> &[Move] {
+        match self.favoured() {
+            Favoured::PlayerToMove => &self.keeping_moves,
+            Favoured::Opponent => &self.flipping_moves,
+        }
+    }
+}
+
+fn odd_or_even(odd: bool) -> &'static str {
+    if odd { "odd" } else { "even" }
+}
+
+fn format_moves(moves: &[Move]) -> String {
+    if moves.is_empty() {
+        "none".to_string()
+    } else {
+        moves.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+impl fmt::Display for ParityAnalysis {
+    fn fmt(self: &ParityAnalysis, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Dots: {}, strings: {}, coins: {}", self.dots, self.strings, self.coins)?;
+        writeln!(f, "Long chains: {} formed, {} potential, {} undecided between chain and loop",
+                 self.long_chains, self.potential_long_chains, self.undecided)?;
+        writeln!(f, "With {} dots the first player wants an {} number of long chains",
+                 self.dots, odd_or_even(self.first_player_wants_odd()))?;
+        writeln!(f, "The player to move wants an {} number of long chains, so with {} expected the rule favours {}",
+                 odd_or_even(self.wants_odd()), self.expected_long_chains(), self.favoured())?;
+        writeln!(f, "Moves which flip parity: {}", format_moves(&self.flipping_moves))?;
+        write!(f, "Moves which keep parity: {}", format_moves(&self.keeping_moves))
+    }
+}
+
+// Analyse a position according to the long chain rule
+pub fn analyse(pos: &SimplePosition) -> ParityAnalysis {
+    let (long_chains, potential_long_chains, undecided) = count_chains(pos);
+    let parity = (long_chains + potential_long_chains) % 2;
+    let mut flipping_moves = Vec::new();
+    let mut keeping_moves = Vec::new();
+    let mut seen: Vec<Move> = Vec::new();
+    let mut pos = pos.clone();
+    for m in pos.legal_moves() {
+        if seen.iter().any(|&n| pos.moves_equivalent(m, n)) {
+            continue;
+        }
+        seen.push(m);
+        if pos.would_capture(m) > 0 {
+            continue;
+        }
+        pos.make_move(m);
+        if !pos.is_loony() {
+            let (chains, potential, _) = count_chains(&pos);
+            if (chains + potential) % 2 == parity {
+                keeping_moves.push(m);
+            } else {
+                flipping_moves.push(m);
+            }
+        }
+        pos.undo_move(m);
+    }
+    ParityAnalysis{
+        dots: (pos.width() + 1) * (pos.height() + 1),
+        strings: pos.legal_move_count(),
+        coins: count_coins(&pos),
+        long_chains: long_chains,
+        potential_long_chains: potential_long_chains,
+        undecided: undecided,
+        flipping_moves: flipping_moves,
+        keeping_moves: keeping_moves,
+    }
+}
+
+fn count_coins(pos: &SimplePosition) -> usize {
+    (0..pos.width()).map(|x| (0..pos.height()).filter(|&y| pos.valency(x, y) > 0).count()).sum()
+}
+
+// Long chains formed, long chains expected and undecided components in a position
+fn count_chains(pos: &SimplePosition) -> (usize, usize, usize) {
+    let mut long_chains = 0;
+    let mut potential = 0;
+    let mut undecided = 0;
+    for part in pos.split() {
+        match structure::classify(&part).kind {
+            StructureKind::LongChain => long_chains += 1,
+            StructureKind::Other => match potential_chains(&part) {
+                Some(n) => potential += n,
+                None => undecided += 1,
+            },
+            StructureKind::ShortChain | StructureKind::Loop => {},
+        }
+    }
+    (long_chains, potential, undecided)
+}
+
+// Coins joined to a given coin by a string
+fn neighbours(pos: &SimplePosition, x: usize, y: usize) -> Vec<(usize, usize)> {
+    Side::all().into_iter()
+        .filter(|&side| pos.is_legal_move(Move::new(x, y, side)))
+        .filter_map(|side| pos.offset(x, y, side))
+        .collect()
+}
+
+// Long chains expected from a single component which is not yet a chain or loop,
+// or None if it is a ring which could still become either a loop or a chain
+fn potential_chains(part: &SimplePosition) -> Option<usize> {
+    let mut coins = Vec::new();
+    for y in 0..part.height() {
+        for x in 0..part.width() {
+            if part.valency(x, y) > 0 {
+                coins.push((x, y));
+            }
+        }
+    }
+    let links: usize = coins.iter().map(|&(x, y)| neighbours(part, x, y).len()).sum::<usize>() / 2;
+    let is_joint = |x: usize, y: usize| neighbours(part, x, y).len() >= 3;
+    if !coins.iter().any(|&(x, y)| is_joint(x, y)) {
+        // A path of coins with extra strings to the ground, or a ring with some
+        return if links < coins.len() {
+            Some((coins.len() >= 3) as usize)
+        } else {
+            None
+        };
+    }
+    // Find the arms left after removing the joints
+    let mut visited: Vec<(usize, usize)> = coins.iter().copied().filter(|&(x, y)| is_joint(x, y)).collect();
+    let mut arms = 0;
+    for &start in coins.iter() {
+        if visited.contains(&start) {
+            continue;
+        }
+        visited.push(start);
+        let mut stack = vec!(start);
+        let mut length = 0;
+        while let Some((x, y)) = stack.pop() {
+            length += 1;
+            for c in neighbours(part, x, y) {
+                if !visited.contains(&c) {
+                    visited.push(c);
+                    stack.push(c);
+                }
+            }
+        }
+        if length >= 3 {
+            arms += 1;
+        }
+    }
+    Some(arms)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::examples::*;
+    use crate::game::*;
+    use crate::parity::*;
+
+    #[test]
+    fn fresh_board_matches_paper_rule() {
+        for (width, height) in [(1, 1), (2, 2), (3, 3), (4, 5), (5, 5)] {
+            let analysis = analyse(&SimplePosition::new_game(width, height));
+            assert_eq!(analysis.first_player_wants_odd(), analysis.wants_odd());
+            assert_eq!(0, analysis.long_chains);
+        }
+        assert_eq!(false, analyse(&SimplePosition::new_game(3, 3)).wants_odd());
+        assert_eq!(true, analyse(&SimplePosition::new_game(4, 4)).wants_odd());
+    }
+
+    #[test]
+    fn chains_and_loops() {
+        let chain = analyse(&make_chain(3));
+        assert_eq!((1, 0, 0), (chain.long_chains, chain.potential_long_chains, chain.undecided));
+        let chains = analyse(&multi_chains(3, 2));
+        assert_eq!(2, chains.long_chains);
+        let short = analyse(&make_chain(2));
+        assert_eq!(0, short.expected_long_chains());
+        let loops = analyse(&double_loop(2));
+        assert_eq!((0, 0, 0), (loops.long_chains, loops.potential_long_chains, loops.undecided));
+    }
+
+    #[test]
+    fn ex3p1_parity() {
+        let pos = ex3p1();
+        let analysis = analyse(&pos);
+        assert_eq!((16, 14, 9), (analysis.dots, analysis.strings, analysis.coins));
+        assert_eq!((0, 1, 1), (analysis.long_chains, analysis.potential_long_chains, analysis.undecided));
+        assert_eq!(false, analysis.wants_odd());
+        assert_eq!(Favoured::Opponent, analysis.favoured());
+        // The solution turns the bottom right into a long chain, gaining control
+        let solution = Move::new(2, 1, Side::Bottom);
+        assert!(analysis.recommended_moves().iter().any(|&m| pos.moves_equivalent(m, solution)));
+        let mut next = pos.clone();
+        next.make_move(solution);
+        let after = analyse(&next);
+        assert_eq!(2, after.expected_long_chains());
+        assert_eq!(Favoured::Opponent, after.favoured());
+    }
+
+    #[test]
+    fn ex3p12_parity() {
+        let pos = ex3p12();
+        let analysis = analyse(&pos);
+        // Three long chains around the two joints, and a top right which could be either
+        assert_eq!((0, 3, 1), (analysis.long_chains, analysis.potential_long_chains, analysis.undecided));
+        assert_eq!(Favoured::Opponent, analysis.favoured());
+        let solution = Move::new(4, 0, Side::Bottom);
+        assert!(analysis.flipping_moves.iter().any(|&m| pos.moves_equivalent(m, solution)));
+        // Taking a ground string in the top right keeps parity
+        assert!(analysis.keeping_moves.iter().any(|&m| pos.moves_equivalent(m, Move::new(4, 0, Side::Right))));
+        let mut next = pos.clone();
+        next.make_move(solution);
+        assert_eq!(4, analyse(&next).expected_long_chains());
+    }
+}
@@ -0,0 +1,258 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::cli;
+use crate::error::Error;
+use crate::eval::{self, EvalTable};
+use crate::game::{Move, Position, Side, SimplePosition};
+
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Line-based protocol for driving the engine from another program, such as a GUI
+// or a tournament manager, in the style of UCI. The commands are:
+//   newgame w h          start a game on a board of width w and height h
+//   position m1 m2 ...   play the given moves from the start of the game
+//   go [movetime n]      search the current position, for at most n milliseconds if given
+//   stop                 finish the search as soon as possible
+//   isready              reply readyok
+//   quit                 stop any search and exit
+// Moves are written x,y,s where s is one of t, l, b, r, for example 0,0,t.
+// When a search finishes it reports
+//   info value v pv m1 m2 ...   if it was completed, where v is the value for the player
+//                               to move and m1 m2 ... a line of optimal play
+//   bestmove m           or bestmove none if the game is over
+// Commands which cannot be carried out are reported as "info string <reason>".
+
+#[derive(PartialEq)]
+#[derive(Debug)]
+enum Command {
+    NewGame(usize, usize),
+    Position(Vec<Move>),
+    Go(Option<u64>),
+    Stop,
+    IsReady,
+    Quit,
+}
+
+// Write a move in protocol notation
+pub fn format_move(m: Move) -> String {
+    let side = match m.side {
+        Side::Top => "t",
+        Side::Left => "l",
+        Side::Bottom => "b",
+        Side::Right => "r",
+    };
+    format!("{},{},{}", m.x, m.y, side)
+}
+
+// Read a move in protocol notation
+pub fn parse_move(input: &str) -> Result<Move, String> {
+    let fields: Vec<&str> = input.split(',').collect();
+    if fields.len() != 3 {
+        return Err(format!("Expected a move of the form x,y,s, found [{}]", input));
+    }
+    let coord = |s: &str| s.parse::<usize>().map_err(|e| format!("Bad coordinate [{}] in [{}]: {}", s, input, e));
+    let side = cli::parse_side(fields[2]).ok_or(format!("Unrecognised side [{}] in [{}]", fields[2], input))?;
+    Ok(Move{x: coord(fields[0])?, y: coord(fields[1])?, side: side})
+}
+
+fn parse_command(input: &str) -> Result<Command, String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let number = |s: &str| s.parse::<usize>().map_err(|e| format!("Cannot parse number [{}]: {}", s, e));
+    match words.as_slice() {
+        ["newgame", w, h] => Ok(Command::NewGame(number(w)?, number(h)?)),
+        ["position", moves @ ..] => moves.iter().map(|m| parse_move(m)).collect::<Result<_, _>>().map(Command::Position),
+        ["go"] => Ok(Command::Go(None)),
+        ["go", "movetime", ms] => Ok(Command::Go(Some(number(ms)? as u64))),
+        ["stop"] => Ok(Command::Stop),
+        ["isready"] => Ok(Command::IsReady),
+        ["quit"] => Ok(Command::Quit),
+        _ => Err(format!("Unknown command [{}]", input)),
+    }
+}
+
+// A search running on its own thread, and the flag which stops it
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+struct Engine<W> {
+    start: SimplePosition,
+    pos: SimplePosition,
+    // Kept across searches, so that each can reuse the work of earlier ones
+    table: Arc<Mutex<EvalTable<Move>>>,
+    search: Option<Search>,
+    output: Arc<Mutex<W>>,
+}
+
+impl<W: Write + Send + 'static> Engine<W> {
+    fn send(self: &Engine<W>, line: &str) {
+        send(&self.output, line);
+    }
+
+    fn execute(self: &mut Engine<W>, command: Command) {
+        match command {
+            Command::NewGame(w, h) => {
+                self.finish_search();
+                match SimplePosition::try_new_game(w, h) {
+                    Ok(pos) => {
+                        self.start = pos.clone();
+                        self.pos = pos;
+                    },
+                    Err(e) => self.send(&format!("info string {}", e)),
+                }
+            },
+            Command::Position(moves) => {
+                self.finish_search();
+                let mut pos = self.start.clone();
+                for m in moves {
+                    if let Err(e) = pos.try_make_move(m) {
+                        self.send(&format!("info string {}", e));
+                        return;
+                    }
+                }
+                self.pos = pos;
+            },
+            Command::Go(movetime) => {
+                self.finish_search();
+                self.start_search(movetime);
+            },
+            Command::Stop => self.stop_search(),
+            Command::IsReady => self.send("readyok"),
+            Command::Quit => self.stop_search(),
+        }
+    }
+
+    fn start_search(self: &mut Engine<W>, movetime: Option<u64>) {
+        let stop = Arc::new(AtomicBool::new(false));
+        if let Some(ms) = movetime {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(ms));
+                stop.store(true, Ordering::Relaxed);
+            });
+        }
+        let pos = self.pos.clone();
+        let table = self.table.clone();
+        let output = self.output.clone();
+        let search_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            let mut table = table.lock().unwrap();
+            let (value, best_move) = eval::eval_until_stopped(&pos, &mut table, &search_stop);
+            match (value, best_move) {
+                (Some(v), Some(m)) => {
+                    let pv: Vec<String> = eval::principal_variation(&pos, &mut table, usize::MAX)
+                        .iter().map(|gm| format_move(gm.m)).collect();
+                    send(&output, &format!("info value {} pv {}", v, pv.join(" ")));
+                    send(&output, &format!("bestmove {}", format_move(m)));
+                },
+                (_, Some(m)) => send(&output, &format!("bestmove {}", format_move(m))),
+                (_, None) => send(&output, "bestmove none"),
+            }
+        });
+        self.search = Some(Search{stop: stop, handle: handle});
+    }
+
+    // Wait for any search to report its result
+    fn finish_search(self: &mut Engine<W>) {
+        if let Some(search) = self.search.take() {
+            search.handle.join().expect("Search thread panicked");
+        }
+    }
+
+    fn stop_search(self: &mut Engine<W>) {
+        if let Some(search) = self.search.as_ref() {
+            search.stop.store(true, Ordering::Relaxed);
+        }
+        self.finish_search();
+    }
+}
+
+fn send<W: Write>(output: &Mutex<W>, line: &str) {
+    let mut output = output.lock().unwrap();
+    // Nothing can be done if the other side has gone away
+    let _ = writeln!(output, "{}", line).and_then(|_| output.flush());
+}
+
+// Read commands from input until quit or the end of the input, writing responses to output.
+// The engine starts with a new game on a 3x3 board.
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) -> Result<(), Error> {
+    let start = SimplePosition::new_game(3, 3);
+    let mut engine = Engine{
+        start: start.clone(),
+        pos: start,
+        table: Arc::new(Mutex::new(EvalTable::default())),
+        search: None,
+        output: Arc::new(Mutex::new(output)),
+    };
+    for line in input.lines() {
+        let line = line.map_err(|e| Error::Io(format!("Could not read command: {}", e)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_command(&line) {
+            Ok(Command::Quit) => {
+                engine.execute(Command::Quit);
+                return Ok(());
+            },
+            Ok(command) => engine.execute(command),
+            Err(e) => engine.send(&format!("info string {}", e)),
+        }
+    }
+    // At the end of the input, let any search finish, so scripts can pipe in commands
+    engine.finish_search();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::*;
+
+    #[test]
+    fn moves() {
+        let m = Move{x: 2, y: 10, side: Side::Right};
+        assert_eq!("2,10,r", format_move(m));
+        assert_eq!(Ok(m), parse_move("2,10,r"));
+        assert_eq!(Ok(Move{x: 0, y: 0, side: Side::Top}), parse_move("0,0,top"));
+        assert!(parse_move("0,0").is_err());
+        assert!(parse_move("0,x,t").is_err());
+        assert!(parse_move("0,0,z").is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(Ok(Command::NewGame(4, 3)), parse_command("newgame 4 3"));
+        assert_eq!(Ok(Command::Position(vec!())), parse_command("position"));
+        assert_eq!(Ok(Command::Position(vec!(Move{x: 0, y: 0, side: Side::Top}, Move{x: 1, y: 0, side: Side::Left}))),
+                   parse_command("position 0,0,t 1,0,l"));
+        assert_eq!(Ok(Command::Go(None)), parse_command("go"));
+        assert_eq!(Ok(Command::Go(Some(250))), parse_command("go  movetime 250"));
+        assert_eq!(Ok(Command::Stop), parse_command("stop"));
+        assert_eq!(Ok(Command::IsReady), parse_command("isready"));
+        assert_eq!(Ok(Command::Quit), parse_command("quit"));
+        assert!(parse_command("newgame 4").is_err());
+        assert!(parse_command("position 0,0").is_err());
+        assert!(parse_command("go movetime soon").is_err());
+        assert!(parse_command("ponder").is_err());
+    }
+}
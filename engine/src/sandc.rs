@@ -0,0 +1,954 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::error::Error;
+use crate::game::{Move, MoveOutcome, Position, SimplePosition, CompoundPosition, Side};
+use crate::splitter::SplittablePosition;
+use crate::nimstring::NimstringPosition;
+use crate::eval::EvaluablePosition;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+// One end of a string: either a coin (identified by index) or the ground
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Eq)]
+#[derive(Hash)]
+#[derive(PartialEq)]
+#[derive(PartialOrd)]
+#[derive(Ord)]
+#[derive(Debug)]
+pub enum Node {
+    Coin(usize),
+    Ground,
+}
+
+impl fmt::Display for Node {
+    fn fmt(self: &Node, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Node::Coin(c) => write!(f, "{}", c),
+            Node::Ground => write!(f, "G"),
+        }
+    }
+}
+
+// A move in a strings-and-coins position: cutting the string with a given index
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Eq)]
+#[derive(Hash)]
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub struct SCMove {
+    pub string: usize,
+}
+
+impl SCMove {
+    pub fn new(string: usize) -> SCMove {
+        SCMove{string}
+    }
+}
+
+impl fmt::Display for SCMove {
+    fn fmt(self: &SCMove, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "String {}", self.string)
+    }
+}
+
+// A strings-and-coins position on an arbitrary multigraph.
+// The coins are numbered 0..coin_count, and each string joins two distinct coins,
+// or a coin and the ground. Any number of strings may join the same pair of nodes,
+// so this can represent positions which have no dots-and-boxes equivalent.
+// Strings keep their index when cut, so moves remain valid across make_move and undo_move.
+#[derive(Clone)]
+pub struct StringsAndCoinsPosition {
+    coin_count: usize,
+    strings: Vec<(Node, Node)>,
+    present: Vec<bool>,
+    coin_strings: Vec<Vec<usize>>,
+    valencies: Vec<usize>,
+    remaining: usize,
+    zhash: GraphZHash,
+}
+
+impl StringsAndCoinsPosition {
+    // Create a position with a given number of coins, with all the given strings present.
+    // Panics if any string joins a node to itself or refers to a coin which does not exist.
+    pub fn new(coin_count: usize, strings: Vec<(Node, Node)>) -> StringsAndCoinsPosition {
+        StringsAndCoinsPosition::try_new(coin_count, strings).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Create a position as for new, or report why the graph is invalid.
+    pub fn try_new(coin_count: usize, strings: Vec<(Node, Node)>) -> Result<StringsAndCoinsPosition, Error> {
+        let mut coin_strings: Vec<Vec<usize>> = vec![Vec::new(); coin_count];
+        for (i, &(a, b)) in strings.iter().enumerate() {
+            if a == b {
+                return Err(Error::InvalidGraph(format!("String {} joins {} to itself", i, a)));
+            }
+            for end in [a, b] {
+                if let Node::Coin(c) = end {
+                    if c >= coin_count {
+                        return Err(Error::InvalidGraph(format!(
+                            "String {} refers to coin {} but there are only {} coins", i, c, coin_count)));
+                    }
+                    coin_strings[c].push(i);
+                }
+            }
+        }
+        let valencies = coin_strings.iter().map(|s| s.len()).collect();
+        let zhash = GraphZHash::new(coin_count, &strings);
+        Ok(StringsAndCoinsPosition {
+            coin_count,
+            present: vec![true; strings.len()],
+            remaining: strings.len(),
+            strings,
+            coin_strings,
+            valencies,
+            zhash,
+        })
+    }
+
+    pub fn coin_count(self: &StringsAndCoinsPosition) -> usize {
+        self.coin_count
+    }
+
+    // The two ends of a given string (whether or not it has been cut)
+    pub fn ends(self: &StringsAndCoinsPosition, m: SCMove) -> (Node, Node) {
+        self.strings[m.string]
+    }
+
+    // Valency or degree of a given coin
+    pub fn valency(self: &StringsAndCoinsPosition, coin: usize) -> usize {
+        self.valencies[coin]
+    }
+
+    // Indicate whether a given coin has been captured.
+    pub fn is_captured(self: &StringsAndCoinsPosition, coin: usize) -> bool {
+        self.valencies[coin] == 0
+    }
+
+    // The strings still attached to a given coin
+    pub fn coin_moves(self: &StringsAndCoinsPosition, coin: usize) -> Vec<SCMove> {
+        self.coin_strings[coin].iter()
+            .filter(|&&s| self.present[s])
+            .map(|&s| SCMove::new(s))
+            .collect()
+    }
+
+    // The node at the other end of a string from a given coin
+    pub fn other_end(self: &StringsAndCoinsPosition, m: SCMove, coin: usize) -> Node {
+        match self.strings[m.string] {
+            (Node::Coin(c), other) if c == coin => other,
+            (other, _) => other,
+        }
+    }
+
+    // The position in the notation read by from_str. Each string still present appears once,
+    // in paths such as G-0-1-2-G, and the coins are renumbered in the order they appear.
+    pub fn notation(self: &StringsAndCoinsPosition) -> String {
+        let mut used: Vec<bool> = self.present.iter().map(|&p| !p).collect();
+        let mut names: Vec<Option<usize>> = vec![None; self.coin_count];
+        let mut next_name = 0;
+        let mut paths = Vec::new();
+        for start in 0..self.strings.len() {
+            if used[start] {
+                continue;
+            }
+            used[start] = true;
+            let (a, b) = self.strings[start];
+            let mut forward = vec![a, b];
+            self.extend_path(&mut forward, &mut used);
+            let mut path = vec![a];
+            self.extend_path(&mut path, &mut used);
+            path.reverse();
+            path.extend_from_slice(&forward[1..]);
+            let nodes: Vec<String> = path.iter().map(|&n| match n {
+                Node::Coin(c) => {
+                    let name = *names[c].get_or_insert(next_name);
+                    if name == next_name {
+                        next_name += 1;
+                    }
+                    name.to_string()
+                },
+                Node::Ground => "G".to_string(),
+            }).collect();
+            paths.push(nodes.join("-"));
+        }
+        paths.join(" ")
+    }
+
+    // Follow unused strings from the coin at the end of a path, until reaching the ground
+    // or a coin with no unused strings left
+    fn extend_path(self: &StringsAndCoinsPosition, path: &mut Vec<Node>, used: &mut [bool]) {
+        while let Some(&Node::Coin(coin)) = path.last() {
+            match self.coin_strings[coin].iter().find(|&&s| !used[s]) {
+                Some(&s) => {
+                    used[s] = true;
+                    path.push(self.other_end(SCMove::new(s), coin));
+                },
+                None => break,
+            }
+        }
+    }
+
+    // Lay out each connected component of the graph on a dots-and-boxes board of its own,
+    // or explain why this cannot be done. Captured coins are left out.
+    pub fn to_grids(self: &StringsAndCoinsPosition) -> Result<Vec<SimplePosition>, Error> {
+        let mut links: Vec<Vec<usize>> = vec![Vec::new(); self.coin_count];
+        let mut ground = vec![0; self.coin_count];
+        for coin in 0..self.coin_count {
+            if self.valency(coin) > 4 {
+                return Err(Error::InvalidGraph(format!(
+                    "Coin {} has {} strings, but a box only has four sides", coin, self.valency(coin))));
+            }
+            for m in self.coin_moves(coin) {
+                match self.other_end(m, coin) {
+                    Node::Coin(c) if links[coin].contains(&c) => return Err(Error::InvalidGraph(format!(
+                        "Coins {} and {} are joined by more than one string", coin, c))),
+                    Node::Coin(c) => links[coin].push(c),
+                    Node::Ground => ground[coin] += 1,
+                }
+            }
+        }
+
+        let mut placed = vec![false; self.coin_count];
+        let mut result = Vec::new();
+        for start in 0..self.coin_count {
+            if placed[start] || self.is_captured(start) {
+                continue;
+            }
+            // Breadth-first order, so that each coin is placed next to one placed before it
+            let mut order = vec![start];
+            placed[start] = true;
+            let mut i = 0;
+            while i < order.len() {
+                for &next in &links[order[i]] {
+                    if !placed[next] {
+                        placed[next] = true;
+                        order.push(next);
+                    }
+                }
+                i += 1;
+            }
+            let mut embedding = Embedding{
+                links: &links,
+                ground: &ground,
+                order: order,
+                location: HashMap::new(),
+                occupied: HashMap::new(),
+                steps: 0,
+            };
+            if !embedding.place(0)? {
+                return Err(Error::InvalidGraph(format!(
+                    "The coins connected to coin {} cannot be laid out on a grid", start)));
+            }
+            result.push(embedding.to_grid()?);
+        }
+        Ok(result)
+    }
+
+    // Coins at the ends of a string, which may capture them when it is cut
+    fn coin_ends(self: &StringsAndCoinsPosition, m: SCMove) -> Vec<usize> {
+        let (a, b) = self.strings[m.string];
+        [a, b].iter().filter_map(|&n| match n {
+            Node::Coin(c) => Some(c),
+            Node::Ground => None,
+        }).collect()
+    }
+}
+
+impl Position<SCMove> for StringsAndCoinsPosition {
+    fn is_legal_move(self: &StringsAndCoinsPosition, m: SCMove) -> bool {
+        m.string < self.strings.len() && self.present[m.string]
+    }
+
+    fn would_capture(self: &StringsAndCoinsPosition, m: SCMove) -> usize {
+        self.coin_ends(m).iter().filter(|&&c| self.valencies[c] == 1).count()
+    }
+
+    fn make_move(self: &mut StringsAndCoinsPosition, m: SCMove) -> MoveOutcome {
+        if !self.is_legal_move(m) {
+            panic!("Illegal move {}, pos:\n{}", m, self);
+        }
+        let captures = self.would_capture(m);
+        self.present[m.string] = false;
+        self.remaining -= 1;
+        for c in self.coin_ends(m) {
+            self.valencies[c] -= 1;
+        }
+        self.zhash.toggle_element(m);
+        let end_of_game = self.is_end_of_game();
+        MoveOutcome {
+            coins_captured: captures,
+            end_of_turn: captures == 0 || end_of_game,
+            end_of_game,
+        }
+    }
+
+    fn undo_move(self: &mut StringsAndCoinsPosition, m: SCMove) {
+        self.present[m.string] = true;
+        self.remaining += 1;
+        for c in self.coin_ends(m) {
+            self.valencies[c] += 1;
+        }
+        self.zhash.toggle_element(m);
+    }
+
+    fn try_undo_move(self: &mut StringsAndCoinsPosition, m: SCMove) -> Result<(), Error> {
+        if m.string < self.strings.len() && !self.present[m.string] {
+            self.undo_move(m);
+            Ok(())
+        } else {
+            Err(Error::MoveNotMade(m.to_string()))
+        }
+    }
+
+    fn is_end_of_game(self: &StringsAndCoinsPosition) -> bool {
+        self.remaining == 0
+    }
+
+    fn legal_moves(self: &StringsAndCoinsPosition) -> Vec<SCMove> {
+        (0..self.strings.len()).filter(|&s| self.present[s]).map(SCMove::new).collect()
+    }
+
+    fn moves_equivalent(self: &StringsAndCoinsPosition, m1: SCMove, m2: SCMove) -> bool {
+        m1 == m2
+    }
+
+    fn zhash(self: &StringsAndCoinsPosition) -> usize {
+        self.zhash.current_val
+    }
+}
+
+impl SplittablePosition<SCMove> for StringsAndCoinsPosition {
+    type Part = StringsAndCoinsPosition;
+    type PartMove = SCMove;
+
+    fn split(self: &StringsAndCoinsPosition) -> Vec<StringsAndCoinsPosition> {
+        let mut component: Vec<Option<usize>> = vec![None; self.coin_count];
+        let mut result = Vec::new();
+        for start in 0..self.coin_count {
+            if component[start].is_some() || self.is_captured(start) {
+                continue;
+            }
+            // Depth-first search for the coins connected to this one,
+            // renumbering them in the order they are found
+            let mut coins = vec![start];
+            component[start] = Some(0);
+            let mut stack = vec![start];
+            while let Some(coin) = stack.pop() {
+                for m in self.coin_moves(coin) {
+                    if let Node::Coin(next) = self.other_end(m, coin) {
+                        if component[next].is_none() {
+                            component[next] = Some(coins.len());
+                            coins.push(next);
+                            stack.push(next);
+                        }
+                    }
+                }
+            }
+            let renumber = |n: Node| match n {
+                Node::Coin(c) => Node::Coin(component[c].unwrap()),
+                Node::Ground => Node::Ground,
+            };
+            let mut strings = Vec::new();
+            for &coin in &coins {
+                for m in self.coin_moves(coin) {
+                    let (a, b) = self.strings[m.string];
+                    // Coin-to-coin strings are seen from both ends but should only be added once
+                    let first_end = match (a, b) {
+                        (Node::Coin(c), _) => c,
+                        (Node::Ground, Node::Coin(c)) => c,
+                        (Node::Ground, Node::Ground) => unreachable!(),
+                    };
+                    if first_end == coin {
+                        strings.push((renumber(a), renumber(b)));
+                    }
+                }
+            }
+            result.push(StringsAndCoinsPosition::new(coins.len(), strings));
+        }
+        result
+    }
+}
+
+impl NimstringPosition<SCMove> for StringsAndCoinsPosition {
+    fn is_loony(self: &StringsAndCoinsPosition) -> bool {
+        for coin in 0..self.coin_count {
+            if self.valency(coin) != 1 {
+                continue;
+            }
+            let string = self.coin_moves(coin)[0];
+            let neighbour = match self.other_end(string, coin) {
+                Node::Coin(n) => n,
+                Node::Ground => continue,
+            };
+            if self.valency(neighbour) != 2 {
+                continue;
+            }
+            // We have found a capturable coin attached to a coin of valency 2 (o-o-?).
+            // This means the position is loony unless there is a valency-1 coin
+            // on the other side (o-o-o).
+            let far_string = self.coin_moves(neighbour).into_iter().find(|&m| m != string).unwrap();
+            if let Node::Coin(far) = self.other_end(far_string, neighbour) {
+                if self.valency(far) == 1 {
+                    continue;
+                }
+            }
+            return true;
+        }
+        false
+    }
+}
+
+impl EvaluablePosition<SCMove> for StringsAndCoinsPosition {
+    fn try_find_ddeal_move(self: &StringsAndCoinsPosition, capture: SCMove) -> Result<SCMove, Error> {
+        if !self.is_legal_move(capture) || self.would_capture(capture) == 0 {
+            return Err(Error::NotLoony(capture.to_string()));
+        }
+        // Either end of the capture might be the valency-1 coin
+        let v2_coin = self.coin_ends(capture).into_iter().find(|&c| self.valency(c) == 2);
+        if let Some(coin) = v2_coin {
+            if let Some(m) = self.coin_moves(coin).into_iter().find(|&m| m != capture) {
+                return Ok(m);
+            }
+        }
+        Err(Error::NotLoony(capture.to_string()))
+    }
+
+    fn is_sacrifice(self: &StringsAndCoinsPosition, m: SCMove) -> bool {
+        self.coin_ends(m).into_iter().any(|c| self.valency(c) == 2)
+    }
+}
+
+impl From<&SimplePosition> for StringsAndCoinsPosition {
+    // Convert a dots-and-boxes position to strings-and-coins form.
+    // The coin for square (x,y) has index y*width + x, and the strings
+    // are those still present, in the order of legal_moves.
+    fn from(pos: &SimplePosition) -> StringsAndCoinsPosition {
+        let coin = |x: usize, y: usize| Node::Coin(y * pos.width() + x);
+        let strings = pos.legal_moves().iter().map(|m: &Move| {
+            match pos.offset(m.x, m.y, m.side) {
+                Some((nx, ny)) => (coin(m.x, m.y), coin(nx, ny)),
+                None => (coin(m.x, m.y), Node::Ground),
+            }
+        }).collect();
+        StringsAndCoinsPosition::new(pos.width() * pos.height(), strings)
+    }
+}
+
+impl From<&CompoundPosition> for StringsAndCoinsPosition {
+    // Convert each part as for SimplePosition, numbering the coins of
+    // each part after those of the parts before it.
+    fn from(pos: &CompoundPosition) -> StringsAndCoinsPosition {
+        let mut coin_count = 0;
+        let mut strings = Vec::new();
+        for part in &pos.parts {
+            let part_graph = StringsAndCoinsPosition::from(part);
+            let shift = |n: Node| match n {
+                Node::Coin(c) => Node::Coin(c + coin_count),
+                Node::Ground => Node::Ground,
+            };
+            strings.extend(part_graph.strings.iter().map(|&(a, b)| (shift(a), shift(b))));
+            coin_count += part_graph.coin_count;
+        }
+        StringsAndCoinsPosition::new(coin_count, strings)
+    }
+}
+
+impl PartialEq for StringsAndCoinsPosition {
+    fn eq(self: &StringsAndCoinsPosition, other: &StringsAndCoinsPosition) -> bool {
+        self.coin_count == other.coin_count
+            && self.strings == other.strings
+            && self.present == other.present
+    }
+}
+
+impl Eq for StringsAndCoinsPosition {}
+
+impl fmt::Display for StringsAndCoinsPosition {
+    fn fmt(self: &StringsAndCoinsPosition, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} coins, strings:", self.coin_count)?;
+        for (i, &(a, b)) in self.strings.iter().enumerate() {
+            if self.present[i] {
+                write!(f, " {}:{}-{}", i, a, b)?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+// Read a position written as a list of paths separated by whitespace or commas, such as
+// "G-a-b-c-G a-d", where G is the ground and any other name made up of letters, digits
+// and underscores is a coin. Each pair of adjacent nodes in a path is joined by a string,
+// and coins are numbered in the order they first appear. A name on its own is a coin
+// with no strings. Anything after a # on a line is a comment.
+impl FromStr for StringsAndCoinsPosition {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<StringsAndCoinsPosition, Error> {
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        let mut strings = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| Error::Parse{line: i + 1, message: message};
+            let line = line.split('#').next().unwrap();
+            for path in line.split(|c: char| c.is_whitespace() || c == ',').filter(|p| !p.is_empty()) {
+                let mut nodes = Vec::new();
+                for name in path.split('-') {
+                    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                        return Err(error(format!("Invalid node [{}] in path [{}]", name, path)));
+                    }
+                    nodes.push(if name == "G" {
+                        Node::Ground
+                    } else {
+                        let next = names.len();
+                        Node::Coin(*names.entry(name).or_insert(next))
+                    });
+                }
+                if nodes == [Node::Ground] {
+                    return Err(error("The ground cannot appear on its own".to_string()));
+                }
+                for pair in nodes.windows(2) {
+                    if pair[0] == pair[1] {
+                        return Err(error(format!("Path [{}] joins a node to itself", path)));
+                    }
+                    strings.push((pair[0], pair[1]));
+                }
+            }
+        }
+        StringsAndCoinsPosition::try_new(names.len(), strings)
+    }
+}
+
+impl TryFrom<&StringsAndCoinsPosition> for SimplePosition {
+    type Error = Error;
+
+    // Convert a graph with a single connected component (or none) to a dots-and-boxes position
+    fn try_from(pos: &StringsAndCoinsPosition) -> Result<SimplePosition, Error> {
+        let mut grids = pos.to_grids()?;
+        match grids.len() {
+            0 => Ok(SimplePosition::new_end_game(1, 1)),
+            1 => Ok(grids.remove(0)),
+            n => Err(Error::InvalidGraph(format!(
+                "Graph has {} separate components, so needs a compound position", n))),
+        }
+    }
+}
+
+impl TryFrom<&StringsAndCoinsPosition> for CompoundPosition {
+    type Error = Error;
+
+    // Convert a graph to a compound position with one part per connected component
+    fn try_from(pos: &StringsAndCoinsPosition) -> Result<CompoundPosition, Error> {
+        pos.to_grids().map(CompoundPosition::new)
+    }
+}
+
+// Effort to spend looking for a layout before giving up on a graph
+const MAX_EMBEDDING_STEPS: usize = 1_000_000;
+
+// Search for a placement of the coins of one connected component on a grid, such that
+// coins joined by a string are in adjacent boxes and coins with ground strings are on
+// the edge of the board. Placing the coins in the search order one by one, each next to
+// one already placed, any placement can be reached up to rotation and reflection.
+struct Embedding<'a> {
+    links: &'a [Vec<usize>],
+    ground: &'a [usize],
+    order: Vec<usize>,
+    location: HashMap<usize, (isize, isize)>,
+    occupied: HashMap<(isize, isize), usize>,
+    steps: usize,
+}
+
+impl<'a> Embedding<'a> {
+    // Smallest and largest x and y coordinates of the coins placed
+    fn bounds(self: &Embedding<'a>) -> (isize, isize, isize, isize) {
+        let xs = self.location.values().map(|l| l.0);
+        let ys = self.location.values().map(|l| l.1);
+        (xs.clone().min().unwrap(), xs.max().unwrap(), ys.clone().min().unwrap(), ys.max().unwrap())
+    }
+
+    // Whether each coin placed is still on enough edges of the board for its ground strings.
+    // Placing more coins can only take coins away from the edges.
+    fn grounds_fit(self: &Embedding<'a>) -> bool {
+        let (min_x, max_x, min_y, max_y) = self.bounds();
+        self.location.iter().all(|(&coin, &(x, y))| {
+            let edges = [x == min_x, x == max_x, y == min_y, y == max_y];
+            edges.iter().filter(|&&e| e).count() >= self.ground[coin]
+        })
+    }
+
+    // Place the coins from a given index of the search order onwards, if possible
+    fn place(self: &mut Embedding<'a>, k: usize) -> Result<bool, Error> {
+        if k == self.order.len() {
+            return Ok(true);
+        }
+        self.steps += 1;
+        if self.steps > MAX_EMBEDDING_STEPS {
+            return Err(Error::InvalidGraph(format!(
+                "Gave up looking for a grid layout for the coins connected to coin {}", self.order[0])));
+        }
+        let coin = self.order[k];
+        let candidates: Vec<(isize, isize)> = if k == 0 {
+            vec!((0, 0))
+        } else {
+            let anchor = self.links[coin].iter().find_map(|n| self.location.get(n)).unwrap();
+            // The second coin may as well go to the right of the first, by symmetry
+            let steps: &[(isize, isize)] = if k == 1 { &[(1, 0)] } else { &[(1, 0), (0, 1), (-1, 0), (0, -1)] };
+            steps.iter().map(|&(dx, dy)| (anchor.0 + dx, anchor.1 + dy)).collect()
+        };
+        for cell in candidates {
+            let adjacent = |l: &(isize, isize)| (l.0 - cell.0).abs() + (l.1 - cell.1).abs() == 1;
+            if self.occupied.contains_key(&cell)
+                || !self.links[coin].iter().all(|n| self.location.get(n).is_none_or(adjacent)) {
+                continue;
+            }
+            self.location.insert(coin, cell);
+            self.occupied.insert(cell, coin);
+            if self.grounds_fit() && self.place(k + 1)? {
+                return Ok(true);
+            }
+            self.location.remove(&coin);
+            self.occupied.remove(&cell);
+        }
+        Ok(false)
+    }
+
+    // Board on which the coins have been placed, with every other string cut
+    fn to_grid(self: &Embedding<'a>) -> Result<SimplePosition, Error> {
+        let (min_x, max_x, min_y, max_y) = self.bounds();
+        let mut pos = SimplePosition::try_new_game((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize)?;
+        let coin_at = |x: usize, y: usize| self.occupied.get(&(x as isize + min_x, y as isize + min_y)).copied();
+        // Ground strings go on edges facing away from the coin's neighbours where possible
+        let mut ground_sides: HashMap<usize, Vec<Side>> = HashMap::new();
+        for (&coin, &(x, y)) in &self.location {
+            let (x, y) = ((x - min_x) as usize, (y - min_y) as usize);
+            let mut sides: Vec<Side> = Side::all().into_iter().filter(|&s| pos.offset(x, y, s).is_none()).collect();
+            sides.sort_by_key(|s| pos.offset(x, y, s.opposite()).and_then(|(nx, ny)| coin_at(nx, ny))
+                .is_none_or(|n| !self.links[coin].contains(&n)));
+            sides.truncate(self.ground[coin]);
+            ground_sides.insert(coin, sides);
+        }
+        for m in pos.legal_moves() {
+            let keep = match (coin_at(m.x, m.y), pos.offset(m.x, m.y, m.side)) {
+                (Some(coin), Some((nx, ny))) => coin_at(nx, ny).is_some_and(|n| self.links[coin].contains(&n)),
+                (Some(coin), None) => ground_sides[&coin].contains(&m.side),
+                (None, _) => false,
+            };
+            if !keep {
+                pos.make_move(m);
+            }
+        }
+        Ok(pos)
+    }
+}
+
+// Zobrist hash for a strings-and-coins position, one random element per string.
+// The random elements are seeded from the structure of the graph, so that
+// positions with the same coins and strings hash equally, but (with high probability)
+// differently from positions on other graphs which share a hash table with them.
+#[derive(Clone)]
+struct GraphZHash {
+    current_val: usize,
+    strings: Vec<usize>,
+}
+
+impl GraphZHash {
+    fn new(coin_count: usize, strings: &[(Node, Node)]) -> GraphZHash {
+        let mut hasher = DefaultHasher::new();
+        coin_count.hash(&mut hasher);
+        strings.hash(&mut hasher);
+        let mut seed: [u8; 32] = [0; 32];
+        seed[0..8].copy_from_slice(&hasher.finish().to_le_bytes());
+        let mut r: StdRng = SeedableRng::from_seed(seed);
+        GraphZHash {
+            strings: (0..strings.len()).map(|_| r.gen()).collect(),
+            current_val: r.gen(),
+        }
+    }
+
+    fn toggle_element(self: &mut GraphZHash, m: SCMove) {
+        self.current_val ^= self.strings[m.string];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sandc::*;
+    use crate::examples::*;
+    use crate::eval;
+    use crate::game::Side;
+    use crate::error::Error;
+    use crate::nimstring::{self, Value};
+
+    // A single coin attached to the ground by a given number of strings
+    fn grounded_coin(ground_links: usize) -> StringsAndCoinsPosition {
+        StringsAndCoinsPosition::new(1, vec![(Node::Coin(0), Node::Ground); ground_links])
+    }
+
+    #[test]
+    fn from_simple_position() {
+        let chain = make_chain(3);
+        let pos = StringsAndCoinsPosition::from(&chain);
+        assert_eq!(3, pos.coin_count());
+        assert_eq!(4, pos.legal_moves().len());
+        assert_eq!(vec!(2, 2, 2), (0..3).map(|c| pos.valency(c)).collect::<Vec<usize>>());
+        assert_eq!(false, pos.is_loony());
+
+        let pos = StringsAndCoinsPosition::from(&SimplePosition::new_game(3, 3));
+        assert_eq!(9, pos.coin_count());
+        assert_eq!(24, pos.legal_moves().len());
+        assert_eq!(4, pos.valency(4));
+        assert_eq!(1, pos.split().len());
+    }
+
+    #[test]
+    fn captures() {
+        let mut pos = StringsAndCoinsPosition::new(2, vec!(
+            (Node::Coin(0), Node::Ground),
+            (Node::Coin(0), Node::Coin(1)),
+            (Node::Coin(1), Node::Coin(0)),
+        ));
+        let outcome = pos.make_move(SCMove::new(0));
+        assert_eq!(0, outcome.coins_captured);
+        assert_eq!(true, outcome.end_of_turn);
+        assert_eq!(false, pos.is_loony());
+        assert_eq!(0, pos.would_capture(SCMove::new(1)));
+        let outcome = pos.make_move(SCMove::new(1));
+        assert_eq!(0, outcome.coins_captured);
+        assert_eq!(2, pos.would_capture(SCMove::new(2)));
+        let outcome = pos.make_move(SCMove::new(2));
+        assert_eq!(2, outcome.coins_captured);
+        assert_eq!(true, outcome.end_of_game);
+        assert_eq!(true, pos.is_captured(0) && pos.is_captured(1));
+
+        pos.undo_move(SCMove::new(2));
+        pos.undo_move(SCMove::new(1));
+        pos.undo_move(SCMove::new(0));
+        assert_eq!(true, pos.eq(&StringsAndCoinsPosition::new(2, vec!(
+            (Node::Coin(0), Node::Ground),
+            (Node::Coin(0), Node::Coin(1)),
+            (Node::Coin(1), Node::Coin(0)),
+        ))));
+    }
+
+    #[test]
+    fn zhash_consistency() {
+        let pos1 = StringsAndCoinsPosition::from(&p50());
+        let mut pos2 = StringsAndCoinsPosition::from(&p50());
+        assert_eq!(pos1.zhash(), pos2.zhash());
+        let m = pos2.legal_moves()[0];
+        pos2.make_move(m);
+        assert!(pos1.zhash() != pos2.zhash());
+        pos2.undo_move(m);
+        assert_eq!(pos1.zhash(), pos2.zhash());
+        assert!(grounded_coin(2).zhash() != grounded_coin(3).zhash());
+    }
+
+    #[test]
+    fn split_graph() {
+        let pos = StringsAndCoinsPosition::from(&p50());
+        let parts = pos.split();
+        assert_eq!(3, parts.len());
+        let mut coin_counts: Vec<usize> = parts.iter().map(|p| p.coin_count()).collect();
+        coin_counts.sort();
+        assert_eq!(vec!(4, 6, 10), coin_counts);
+    }
+
+    #[test]
+    fn matches_simple_position() {
+        let positions = vec!(ex3p1(), p50_top(), p50_bottomleft(), p50_bottomright(),
+                             double_chain(4), double_loop(3), icelandic_game(2, 2));
+        for simple in positions {
+            let graph = StringsAndCoinsPosition::from(&simple);
+            assert_eq!(eval::eval(&simple).0, eval::eval(&graph).0, "{}", simple);
+            assert_eq!(nimstring::calc_value_with_moves(&simple).0,
+                       nimstring::calc_value_with_moves(&graph).0, "{}", simple);
+        }
+
+        let compound = one_long_multi_three(3, 4);
+        let graph = StringsAndCoinsPosition::from(&compound);
+        assert_eq!(eval::eval(&compound).0, eval::eval(&graph).0);
+    }
+
+    #[test]
+    fn opened_chain_ddeal() {
+        let mut simple = make_chain(4);
+        simple.make_move(Move::new(0, 0, Side::Left));
+        let mut pos = StringsAndCoinsPosition::from(&make_chain(4));
+        // Strings of the 4-chain in legal_moves order: 0 is the left ground link,
+        // 1, 2 and 3 join the coins, 4 is the right ground link
+        pos.make_move(SCMove::new(0));
+        assert_eq!(true, pos.is_loony());
+        assert_eq!(SCMove::new(2), pos.find_ddeal_move(SCMove::new(1)));
+        assert_eq!(eval::eval(&simple).0, eval::eval(&pos).0);
+    }
+
+    #[test]
+    fn multiple_ground_links() {
+        // Impossible on a dots-and-boxes board: a lone coin with three ground links
+        assert_eq!(Value::Nimber(1), nimstring::calc_value_with_moves(&grounded_coin(2)).0);
+        assert_eq!(Value::Nimber(0), nimstring::calc_value_with_moves(&grounded_coin(3)).0);
+        assert_eq!(-1, eval::eval(&grounded_coin(2)).0);
+        assert_eq!(1, eval::eval(&grounded_coin(3)).0);
+    }
+
+    // Dumb evaluation algorithm to compare to optimised one
+    fn naive_minimax(pos: &mut StringsAndCoinsPosition) -> isize {
+        let moves = pos.legal_moves();
+        if moves.is_empty() {
+            return 0;
+        }
+        let mut result = isize::MIN;
+        for m in moves {
+            let outcome = pos.make_move(m);
+            let captures = outcome.coins_captured as isize;
+            let m_val = if captures > 0 { captures + naive_minimax(pos) } else { -naive_minimax(pos) };
+            pos.undo_move(m);
+            result = result.max(m_val);
+        }
+        result
+    }
+
+    #[test]
+    fn non_grid_graph() {
+        // A coin of valency 5, with two loops of two coins hanging off it,
+        // which could not be drawn on a dots-and-boxes board
+        let c = Node::Coin;
+        let mut pos = StringsAndCoinsPosition::new(5, vec!(
+            (c(0), Node::Ground),
+            (c(0), c(1)), (c(1), c(2)), (c(2), c(0)),
+            (c(0), c(3)), (c(3), c(4)), (c(4), c(0)),
+        ));
+        assert_eq!(5, pos.valency(0));
+        let (val, per_move) = nimstring::calc_value_with_moves(&pos);
+        assert_eq!(7, per_move.len());
+        assert!(!val.is_loony());
+        let (val, best_move) = eval::eval(&pos);
+        assert!(pos.is_legal_move(best_move.unwrap()));
+        assert_eq!(naive_minimax(&mut pos), val);
+
+        // Strings joining non-adjacent coins of a chain
+        let mut pos = StringsAndCoinsPosition::new(4, vec!(
+            (c(0), Node::Ground), (c(0), c(1)), (c(1), c(2)), (c(2), c(3)), (c(3), Node::Ground),
+            (c(0), c(2)), (c(1), c(3)), (c(0), c(3)),
+        ));
+        assert_eq!(naive_minimax(&mut pos), eval::eval(&pos).0);
+    }
+
+    #[test]
+    fn invalid_graphs() {
+        let result = StringsAndCoinsPosition::try_new(2, vec!((Node::Coin(0), Node::Coin(2))));
+        assert_eq!(Err(Error::InvalidGraph("String 0 refers to coin 2 but there are only 2 coins".to_string())),
+                   result.map(|_| ()));
+        let result = StringsAndCoinsPosition::try_new(2, vec!((Node::Coin(0), Node::Coin(1)), (Node::Ground, Node::Ground)));
+        assert_eq!(Err(Error::InvalidGraph("String 1 joins G to itself".to_string())), result.map(|_| ()));
+
+        let mut pos = StringsAndCoinsPosition::try_new(1, vec!((Node::Coin(0), Node::Ground))).unwrap();
+        assert_eq!(Err(Error::MoveNotMade("String 0".to_string())), pos.try_undo_move(SCMove{string: 0}));
+        assert_eq!(Err(Error::IllegalMove("String 1".to_string())), pos.try_make_move(SCMove{string: 1}));
+        assert_eq!(Err(Error::NotLoony("String 0".to_string())), pos.try_find_ddeal_move(SCMove{string: 0}));
+    }
+
+    #[test]
+    fn notation() {
+        let chain = StringsAndCoinsPosition::from(&make_chain(3));
+        assert_eq!("G-0-1-2-G", chain.notation());
+        let pos: StringsAndCoinsPosition = "G-a-b-c-G".parse().unwrap();
+        assert_eq!(chain.notation(), pos.notation());
+
+        // Comments, commas, coins without strings, and paths through the ground
+        let pos: StringsAndCoinsPosition = "# Dipper\nG-top-x-y-z-w-x, lonely\n\nG-q-G # Grounded twice".parse().unwrap();
+        assert_eq!(7, pos.coin_count());
+        assert_eq!(8, pos.legal_moves().len());
+        assert_eq!(vec!(2, 3, 2, 2, 2, 0, 2), (0..7).map(|c| pos.valency(c)).collect::<Vec<usize>>());
+        assert_eq!("G-0-1-2-3-4-1 G-5-G", pos.notation());
+
+        // Printing renumbers coins by appearance, after which the notation is stable
+        let mut pos = StringsAndCoinsPosition::from(&ex3p1());
+        let moves = pos.legal_moves();
+        pos.make_move(moves[3]);
+        let text = pos.notation();
+        let reparsed: StringsAndCoinsPosition = text.parse().unwrap();
+        assert_eq!(text, reparsed.notation());
+        assert_eq!(eval::eval(&pos).0, eval::eval(&reparsed).0);
+        assert_eq!("", StringsAndCoinsPosition::from(&SimplePosition::new_end_game(2, 2)).notation());
+    }
+
+    #[test]
+    fn notation_errors() {
+        let parse = |text: &str| text.parse::<StringsAndCoinsPosition>().map(|_| ());
+        assert_eq!(Err(Error::Parse{line: 2, message: "Invalid node [] in path [a--b]".to_string()}),
+                   parse("G-a\na--b"));
+        assert_eq!(Err(Error::Parse{line: 1, message: "Invalid node [b!] in path [a-b!]".to_string()}),
+                   parse("a-b!"));
+        assert_eq!(Err(Error::Parse{line: 1, message: "The ground cannot appear on its own".to_string()}),
+                   parse("a-b G"));
+        assert_eq!(Err(Error::Parse{line: 1, message: "Path [a-b-b] joins a node to itself".to_string()}),
+                   parse("a-b-b"));
+    }
+
+    #[test]
+    fn to_grids() {
+        // Paper examples survive a round trip through the notation
+        for simple in vec!(make_chain(3), ex3p1(), p50_top(), icelandic_game(2, 2)) {
+            let graph: StringsAndCoinsPosition = StringsAndCoinsPosition::from(&simple).notation().parse().unwrap();
+            let grid = CompoundPosition::try_from(&graph).unwrap();
+            assert_eq!(eval::eval(&simple).0, eval::eval(&grid).0, "{}", simple);
+            assert_eq!(nimstring::calc_value_with_moves(&simple).0,
+                       nimstring::calc_value_with_moves(&grid).0, "{}", simple);
+        }
+        // Chains are laid out the way they would be drawn
+        let chain: StringsAndCoinsPosition = "G-a-b-c-G".parse().unwrap();
+        assert_eq!(true, make_chain(3).eq(&SimplePosition::try_from(&chain).unwrap()));
+
+        let graph = StringsAndCoinsPosition::from(&double_loop(3));
+        assert_eq!(Err(Error::InvalidGraph("Graph has 2 separate components, so needs a compound position".to_string())),
+                   SimplePosition::try_from(&graph).map(|_| ()));
+        let compound = CompoundPosition::try_from(&graph).unwrap();
+        assert_eq!(2, compound.parts.len());
+        assert_eq!(eval::eval(&double_loop(3)).0, eval::eval(&compound).0);
+
+        let dipper: StringsAndCoinsPosition = "G-a-b-c-d-e-b".parse().unwrap();
+        let grid = SimplePosition::try_from(&dipper).unwrap();
+        assert_eq!((6, 6), (grid.width() * grid.height(), grid.legal_moves().len()));
+        assert_eq!(eval::eval(&dipper).0, eval::eval(&grid).0);
+        assert_eq!(true, SimplePosition::try_from(&StringsAndCoinsPosition::new(0, vec!())).unwrap().is_end_of_game());
+    }
+
+    #[test]
+    fn not_grids() {
+        let grid = |text: &str| CompoundPosition::try_from(&text.parse::<StringsAndCoinsPosition>().unwrap()).map(|_| ());
+        assert_eq!(Err(Error::InvalidGraph("Coin 0 has 5 strings, but a box only has four sides".to_string())),
+                   CompoundPosition::try_from(&grounded_coin(5)).map(|_| ()));
+        assert_eq!(Err(Error::InvalidGraph("Coins 0 and 1 are joined by more than one string".to_string())),
+                   grid("G-a-b-a"));
+        // Boxes of a grid can be coloured like a chessboard, so there are no odd cycles
+        assert_eq!(Err(Error::InvalidGraph("The coins connected to coin 1 cannot be laid out on a grid".to_string())),
+                   grid("G-G2 a-b-c-a"));
+        // A coin with a ground string which is surrounded by others
+        assert_eq!(Err(Error::InvalidGraph("The coins connected to coin 0 cannot be laid out on a grid".to_string())),
+                   grid("a-b-c d-e-f g-h-i a-d-g b-e c-f-i G-e"));
+        assert_eq!(Ok(()), grid("G-a-b-c-G, b-d-G, b-e-G"));
+    }
+}
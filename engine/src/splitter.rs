@@ -17,17 +17,25 @@
     along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
 */
 use crate::game::{Move, CPosMove, Position, CompoundPosition, SimplePosition, Side};
+use crate::nimstring::NimstringPosition;
 use std::cmp;
 use std::iter;
 
 pub trait SplittablePosition<M> : Position<M> {
+    // Type of the independent fragments a position splits into, and of their moves
+    type Part: NimstringPosition<Self::PartMove>;
+    type PartMove: Copy;
+
     // Split a Position into its independent fragments.
     // If the position is fully connected, the result will consist of a single element
     // representing the whole position.
-    fn split(&self) -> Vec<SimplePosition>;
+    fn split(&self) -> Vec<Self::Part>;
 }
 
 impl SplittablePosition<Move> for SimplePosition {
+    type Part = SimplePosition;
+    type PartMove = Move;
+
     fn split(self: &SimplePosition) -> Vec<SimplePosition> {
         let mut visited: Vec<Vec<bool>> = Vec::with_capacity(self.width());
         for _ in 0..self.width() {
@@ -48,6 +56,9 @@ impl SplittablePosition<Move> for SimplePosition {
 }
 
 impl SplittablePosition<CPosMove> for CompoundPosition {
+    type Part = SimplePosition;
+    type PartMove = Move;
+
     fn split(self: &CompoundPosition) -> Vec<SimplePosition> {
         let mut new_parts = Vec::with_capacity(self.parts.len());
         for part in &self.parts {
@@ -0,0 +1,248 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::game::{Move, Position, SimplePosition, Side};
+use crate::splitter::SplittablePosition;
+use std::fmt;
+
+// The kinds of component distinguished by the endgame theory in the paper.
+// Chains of at least three coins are long; loops always have at least four coins
+// in dots-and-boxes, so are always long.
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+pub enum StructureKind {
+    ShortChain,
+    LongChain,
+    Loop,
+    // Anything containing a joint, i.e. a coin of valency 3 or more
+    Other,
+}
+
+impl fmt::Display for StructureKind {
+    fn fmt(self: &StructureKind, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StructureKind::ShortChain => write!(f, "short chain"),
+            StructureKind::LongChain => write!(f, "long chain"),
+            StructureKind::Loop => write!(f, "loop"),
+            StructureKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+// Description of one independent component of a position.
+// A component is open if some coin in it can be captured immediately.
+// A chain or loop of at least four coins which has been cut so that both ends can be
+// captured (an opened loop, in the paper's terms) is reported as an open loop.
+// Anything shorter can only have been a chain, so is reported as an open chain.
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub struct Structure {
+    pub kind: StructureKind,
+    pub length: usize,
+    pub open: bool,
+}
+
+impl fmt::Display for Structure {
+    fn fmt(self: &Structure, f: &mut fmt::Formatter) -> fmt::Result {
+        let coins = format!("{} coin{}", self.length, if self.length == 1 { "" } else { "s" });
+        match (self.kind, self.open) {
+            (StructureKind::Other, false) => write!(f, "other structure of {}", coins),
+            (StructureKind::Other, true) => write!(f, "other structure of {}, some capturable", coins),
+            (kind, open) => write!(f, "{} {} of {}", if open { "open" } else { "closed" }, kind, coins),
+        }
+    }
+}
+
+// Classification of each component of a position, in the order produced by split
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub struct Analysis {
+    pub structures: Vec<Structure>,
+}
+
+impl Analysis {
+    // Number of long chains, as counted by the chain parity rules
+    pub fn long_chain_count(self: &Analysis) -> usize {
+        self.count(StructureKind::LongChain)
+    }
+
+    // Number of components of a given kind
+    pub fn count(self: &Analysis, kind: StructureKind) -> usize {
+        self.structures.iter().filter(|s| s.kind == kind).count()
+    }
+
+    // Indicate whether every component is a chain or a loop
+    pub fn is_simple(self: &Analysis) -> bool {
+        self.count(StructureKind::Other) == 0
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(self: &Analysis, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, s) in self.structures.iter().enumerate() {
+            writeln!(f, "Component {}: {}", i, s)?;
+        }
+        write!(f, "Long chains: {}", self.long_chain_count())
+    }
+}
+
+// Classify each independent component of a position
+pub fn analyse<M, P>(pos: &P) -> Analysis
+where P: SplittablePosition<M, Part = SimplePosition> {
+    Analysis{structures: pos.split().iter().map(classify).collect()}
+}
+
+// Classify a position consisting of a single connected component
+pub(crate) fn classify(pos: &SimplePosition) -> Structure {
+    let mut length = 0;
+    let mut open = false;
+    let mut has_joint = false;
+    let mut ground_links = 0;
+    let mut coin_links = 0;
+    for x in 0..pos.width() {
+        for y in 0..pos.height() {
+            let valency = pos.valency(x, y);
+            if valency == 0 {
+                continue;
+            }
+            length += 1;
+            open |= valency == 1;
+            has_joint |= valency > 2;
+            for side in Side::all() {
+                if pos.is_legal_move(Move{x: x, y: y, side: side}) {
+                    match pos.offset(x, y, side) {
+                        Some(_) => coin_links += 1,
+                        None => ground_links += 1,
+                    }
+                }
+            }
+        }
+    }
+    // Strings between two coins were counted from both ends
+    let coin_links = coin_links / 2;
+
+    let kind = if has_joint {
+        StructureKind::Other
+    } else if coin_links >= length || (ground_links == 0 && length >= 4) {
+        // Either a closed circuit, or a path long enough to be one, cut at both ends
+        StructureKind::Loop
+    } else if length >= 3 {
+        StructureKind::LongChain
+    } else {
+        StructureKind::ShortChain
+    };
+    Structure{kind: kind, length: length, open: open}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structure::*;
+    use crate::examples::*;
+    use crate::game::CompoundPosition;
+
+    fn structure(kind: StructureKind, length: usize, open: bool) -> Structure {
+        Structure{kind: kind, length: length, open: open}
+    }
+
+    #[test]
+    fn chains() {
+        for i in 1..6 {
+            let expected_kind = if i >= 3 { StructureKind::LongChain } else { StructureKind::ShortChain };
+            let mut pos = make_chain(i);
+            assert_eq!(vec!(structure(expected_kind, i, false)), analyse(&pos).structures);
+            pos.make_move(Move{x: 0, y: 0, side: Side::Left});
+            assert_eq!(vec!(structure(expected_kind, i, true)), analyse(&pos).structures);
+            if i >= 2 {
+                // Cut at both ends, it is only taken for an opened loop if long enough to be one
+                pos.make_move(Move{x: i - 1, y: 0, side: Side::Right});
+                let kind = if i >= 4 { StructureKind::Loop } else { expected_kind };
+                assert_eq!(vec!(structure(kind, i, true)), analyse(&pos).structures);
+            }
+        }
+        let analysis = analyse(&multi_chains(4, 3));
+        assert_eq!(3, analysis.long_chain_count());
+        assert_eq!(true, analysis.is_simple());
+        assert_eq!(0, analyse(&multi_chains(2, 3)).long_chain_count());
+    }
+
+    #[test]
+    fn loops() {
+        let mut pos = double_loop(3);
+        let analysis = analyse(&pos);
+        assert_eq!(vec!(structure(StructureKind::Loop, 6, false), structure(StructureKind::Loop, 6, false)),
+                   analysis.structures);
+        assert_eq!(0, analysis.long_chain_count());
+        // Cutting a loop leaves both ends capturable
+        pos.make_move(Move{x: 0, y: 0, side: Side::Right});
+        let analysis = analyse(&pos);
+        assert_eq!(1, analysis.structures.iter().filter(|&s| s == &structure(StructureKind::Loop, 6, true)).count());
+    }
+
+    #[test]
+    fn compound() {
+        let pos = one_long_multi_three(3, 5);
+        let analysis = analyse(&pos);
+        assert_eq!(structure(StructureKind::LongChain, 5, false), analysis.structures[0]);
+        assert_eq!(4, analysis.long_chain_count());
+
+        let pos = CompoundPosition::new(vec!(make_chain(2), make_chain(1)));
+        assert_eq!(0, analyse(&pos).long_chain_count());
+        assert_eq!(2, analyse(&pos).count(StructureKind::ShortChain));
+    }
+
+    #[test]
+    fn other_structures() {
+        let analysis = analyse(&SimplePosition::new_game(2, 2));
+        assert_eq!(vec!(structure(StructureKind::Other, 4, false)), analysis.structures);
+        assert_eq!(false, analysis.is_simple());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("closed long chain of 3 coins", format!("{}", structure(StructureKind::LongChain, 3, false)));
+        assert_eq!("open short chain of 1 coin", format!("{}", structure(StructureKind::ShortChain, 1, true)));
+        assert_eq!("other structure of 9 coins", format!("{}", structure(StructureKind::Other, 9, false)));
+        let analysis = analyse(&CompoundPosition::new(vec!(make_chain(4), double_loop(2))));
+        assert_eq!("Component 0: closed long chain of 4 coins\nComponent 1: closed loop of 4 coins\n\
+                    Component 2: closed loop of 4 coins\nLong chains: 1", format!("{}", analysis));
+    }
+
+    #[test]
+    fn book_examples() {
+        let summary = |pos: &SimplePosition| {
+            let mut summary: Vec<(StructureKind, usize)> = analyse(pos).structures.iter().map(|s| (s.kind, s.length)).collect();
+            summary.sort_by_key(|&(_, length)| length);
+            summary
+        };
+        // Positions from the book still have joints, so the chains are yet to be resolved
+        assert_eq!(vec!((StructureKind::Other, 4), (StructureKind::Other, 5)), summary(&ex3p1()));
+        assert_eq!(vec!((StructureKind::Other, 4), (StructureKind::Other, 6), (StructureKind::Other, 10)), summary(&p50()));
+        let ex3p12 = analyse(&ex3p12());
+        assert_eq!(3, ex3p12.count(StructureKind::Other));
+        assert_eq!(0, ex3p12.long_chain_count());
+        assert_eq!(false, ex3p12.structures.iter().any(|s| s.open));
+    }
+}
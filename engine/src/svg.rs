@@ -0,0 +1,434 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use crate::game::{Position, SimplePosition, CompoundPosition, Move, CPosMove, Side, Game};
+use std::collections::HashMap;
+use std::fmt;
+
+// SVG diagrams of positions, along the lines of those drawn by svg.py for the paper.
+// Each grid of a position is drawn side by side, either as dots-and-boxes (with the
+// strings which have been cut drawn as lines) or as strings-and-coins (with the strings
+// still present drawn as links between coins, or as arrows to the ground).
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub enum Style {
+    DotsAndBoxes,
+    StringsAndCoins,
+}
+
+// A position made up of one or more grids which can be drawn
+pub trait Drawable {
+    fn grids(&self) -> Vec<&SimplePosition>;
+}
+
+impl Drawable for SimplePosition {
+    fn grids(self: &SimplePosition) -> Vec<&SimplePosition> {
+        vec!(self)
+    }
+}
+
+impl Drawable for CompoundPosition {
+    fn grids(self: &CompoundPosition) -> Vec<&SimplePosition> {
+        self.parts.iter().collect()
+    }
+}
+
+// A move on a Drawable position, identified by the index of its grid and the move within it
+pub trait GridMove {
+    fn locate(self) -> (usize, Move);
+}
+
+impl GridMove for Move {
+    fn locate(self: Move) -> (usize, Move) {
+        (0, self)
+    }
+}
+
+impl GridMove for CPosMove {
+    fn locate(self: CPosMove) -> (usize, Move) {
+        (self.part, self.m)
+    }
+}
+
+// A dot, or the centre of a coin, in units of the gap between them
+type Point = (usize, usize);
+
+const COIN_RADIUS: usize = 10;
+const DOT_RADIUS: usize = 3;
+const THICKNESS: usize = 1;
+const HIGHLIGHT_THICKNESS: usize = 3;
+const CAPTURED_FILL: &str = "lightgrey";
+
+pub struct Diagram {
+    style: Style,
+    // Distance between adjacent dots or coins
+    gap: usize,
+    grids: Vec<SimplePosition>,
+    highlights: Vec<(usize, Move)>,
+    // Initials of the players who captured boxes, keyed by grid and coordinates
+    owners: HashMap<(usize, usize, usize), char>,
+    labels: Vec<String>,
+}
+
+impl Diagram {
+    pub fn new<P: Drawable>(pos: &P, style: Style) -> Diagram {
+        Diagram{
+            style: style,
+            gap: 50,
+            grids: pos.grids().into_iter().cloned().collect(),
+            highlights: Vec::new(),
+            owners: HashMap::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    // Diagram of the current position of a game, in which captured boxes are marked with
+    // the initial of the player who took them, the moves of the last turn are highlighted,
+    // and the score is given underneath
+    pub fn from_game<M, P>(game: &Game<M, P>, style: Style) -> Diagram
+    where M: Copy + fmt::Display + GridMove, P: Position<M> + Drawable + Clone {
+        let mut diagram = Diagram::new(game.position(), style);
+        let players = game.players();
+        let initials: Vec<char> = players.iter()
+            .map(|p| p.chars().next().map_or('?', |c| c.to_ascii_uppercase())).collect();
+
+        // Replay the game from the start to find out who captured what
+        let mut pos = game.position().clone();
+        for gm in game.moves().iter().rev() {
+            pos.undo_move(gm.m);
+        }
+        for gm in game.moves() {
+            pos.make_move(gm.m);
+            if gm.outcome.coins_captured > 0 {
+                let (part, m) = gm.m.locate();
+                let grid = pos.grids()[part];
+                let boxes = vec!(Some((m.x, m.y)), grid.offset(m.x, m.y, m.side));
+                for (x, y) in boxes.into_iter().flatten() {
+                    if grid.is_captured(x, y) {
+                        diagram.owners.entry((part, x, y)).or_insert(initials[gm.player]);
+                    }
+                }
+            }
+        }
+
+        if let Some(last) = game.moves().last() {
+            for gm in game.moves().iter().rev().take_while(|gm| gm.player == last.player) {
+                diagram.highlight(gm.m);
+            }
+        }
+        diagram.label(&format!("{} {} - {} {}", players[0], game.score(0), game.score(1), players[1]));
+        diagram
+    }
+
+    // Distance between adjacent dots or coins (50 by default)
+    pub fn gap(self: &mut Diagram, gap: usize) -> &mut Diagram {
+        self.gap = gap;
+        self
+    }
+
+    // Draw the string cut by a move with a thicker line
+    pub fn highlight<M: GridMove>(self: &mut Diagram, m: M) -> &mut Diagram {
+        self.highlights.push(m.locate());
+        self
+    }
+
+    // Mark a captured box with the initial of the player who took it
+    pub fn owner(self: &mut Diagram, part: usize, x: usize, y: usize, initial: char) -> &mut Diagram {
+        self.owners.insert((part, x, y), initial);
+        self
+    }
+
+    // Add a line of text underneath the diagram
+    pub fn label(self: &mut Diagram, text: &str) -> &mut Diagram {
+        self.labels.push(text.to_string());
+        self
+    }
+
+    fn is_highlighted(self: &Diagram, part: usize, m: Move) -> bool {
+        let grid = &self.grids[part];
+        self.highlights.iter().any(|&(p, h)| p == part && (h == m || grid.moves_equivalent(h, m)))
+    }
+
+    // Width and height of the area taken up by a grid
+    fn extent(self: &Diagram, grid: &SimplePosition) -> (usize, usize) {
+        // Strings-and-coins needs room for the ground strings around the edge
+        let border = if self.style == Style::StringsAndCoins { 1 } else { 0 };
+        ((grid.width() + border) * self.gap, (grid.height() + border) * self.gap)
+    }
+
+    fn label_height(self: &Diagram) -> usize {
+        self.gap / 2
+    }
+
+    fn margin(self: &Diagram) -> usize {
+        self.gap / 2
+    }
+
+    fn size(self: &Diagram) -> (usize, usize) {
+        let extents: Vec<(usize, usize)> = self.grids.iter().map(|g| self.extent(g)).collect();
+        let width = extents.iter().map(|e| e.0).sum::<usize>() + self.gap * extents.len().saturating_sub(1);
+        let height = extents.iter().map(|e| e.1).max().unwrap_or(0) + self.label_height() * self.labels.len();
+        (width + 2 * self.margin(), height + 2 * self.margin())
+    }
+
+    // Every string of a grid once, with its end points relative to the top left dot
+    fn strings(grid: &SimplePosition) -> Vec<(Move, Point, Point)> {
+        let mut result = Vec::new();
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                let mut sides = vec!(Side::Top, Side::Left);
+                if x + 1 == grid.width() {
+                    sides.push(Side::Right);
+                }
+                if y + 1 == grid.height() {
+                    sides.push(Side::Bottom);
+                }
+                for side in sides {
+                    let ends = match side {
+                        Side::Top => ((x, y), (x + 1, y)),
+                        Side::Bottom => ((x, y + 1), (x + 1, y + 1)),
+                        Side::Left => ((x, y), (x, y + 1)),
+                        Side::Right => ((x + 1, y), (x + 1, y + 1)),
+                    };
+                    result.push((Move::new(x, y, side), ends.0, ends.1));
+                }
+            }
+        }
+        result
+    }
+
+    fn write_dots_and_boxes(self: &Diagram, f: &mut fmt::Formatter, part: usize, left: usize, top: usize) -> fmt::Result {
+        let grid = &self.grids[part];
+        let gap = self.gap;
+        let point = |(x, y): (usize, usize)| (left + x * gap, top + y * gap);
+        let boxes = (0..grid.height()).flat_map(|y| (0..grid.width()).map(move |x| (x, y)));
+        for (x, y) in boxes.clone().filter(|&(x, y)| grid.is_captured(x, y)) {
+            let (px, py) = point((x, y));
+            writeln!(f, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, px, py, gap, gap, CAPTURED_FILL)?;
+        }
+        for (m, start, end) in Diagram::strings(grid) {
+            let highlighted = self.is_highlighted(part, m);
+            if highlighted || !grid.is_legal_move(m) {
+                let thickness = if highlighted { HIGHLIGHT_THICKNESS } else { THICKNESS };
+                write_line(f, point(start), point(end), thickness)?;
+            }
+        }
+        for y in 0..=grid.height() {
+            for x in 0..=grid.width() {
+                let (px, py) = point((x, y));
+                writeln!(f, r#"<circle cx="{}" cy="{}" r="{}" fill="black"/>"#, px, py, DOT_RADIUS)?;
+            }
+        }
+        for (x, y) in boxes {
+            if let Some(initial) = self.owners.get(&(part, x, y)) {
+                let (px, py) = point((x, y));
+                // Baseline offset to centre the text vertically, as dominant-baseline is not widely supported
+                writeln!(f, r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                         px + gap / 2, py + gap / 2 + 5, escape(&initial.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_strings_and_coins(self: &Diagram, f: &mut fmt::Formatter, part: usize, left: usize, top: usize) -> fmt::Result {
+        let grid = &self.grids[part];
+        let gap = self.gap;
+        let centre = |x: usize, y: usize| (left + (x + 1) * gap, top + (y + 1) * gap);
+        // Captured coins are left out, unless they were taken by a highlighted move
+        let mut shown_captures = Vec::new();
+        for (m, _, _) in Diagram::strings(grid) {
+            let highlighted = self.is_highlighted(part, m);
+            if !highlighted && !grid.is_legal_move(m) {
+                continue;
+            }
+            let thickness = if highlighted { HIGHLIGHT_THICKNESS } else { THICKNESS };
+            let start = centre(m.x, m.y);
+            match grid.offset(m.x, m.y, m.side) {
+                Some((nx, ny)) => {
+                    write_line(f, start, centre(nx, ny), thickness)?;
+                    if highlighted {
+                        shown_captures.push((nx, ny));
+                    }
+                },
+                None => write_ground_link(f, start, m.side, gap, thickness)?,
+            }
+            if highlighted {
+                shown_captures.push((m.x, m.y));
+            }
+        }
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                let captured = grid.is_captured(x, y);
+                if captured && !shown_captures.contains(&(x, y)) {
+                    continue;
+                }
+                let (cx, cy) = centre(x, y);
+                let thickness = if captured { HIGHLIGHT_THICKNESS } else { THICKNESS };
+                writeln!(f, r#"<circle cx="{}" cy="{}" r="{}" stroke="black" stroke-width="{}" fill="white"/>"#,
+                         cx, cy, COIN_RADIUS, thickness)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_line(f: &mut fmt::Formatter, (x1, y1): (usize, usize), (x2, y2): (usize, usize), thickness: usize) -> fmt::Result {
+    writeln!(f, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="{}"/>"#, x1, y1, x2, y2, thickness)
+}
+
+// A string from a coin to the ground, drawn as an arrow pointing away from the coin
+fn write_ground_link(f: &mut fmt::Formatter, coin: (usize, usize), side: Side, length: usize, thickness: usize) -> fmt::Result {
+    let (dx, dy): (isize, isize) = match side {
+        Side::Left => (-1, 0),
+        Side::Right => (1, 0),
+        Side::Top => (0, -1),
+        Side::Bottom => (0, 1),
+    };
+    let (cx, cy) = (coin.0 as isize, coin.1 as isize);
+    let (px, py) = (cx + dx * length as isize, cy + dy * length as isize);
+    // The feathers of the arrow point back from the tip at either side
+    let feather = (length / 5).max(1) as isize;
+    let (fx, fy) = (px - feather * dx, py - feather * dy);
+    let (sx, sy) = (feather * dy.abs() / 2, feather * dx.abs() / 2);
+    let tip = (px as usize, py as usize);
+    write_line(f, coin, tip, thickness)?;
+    write_line(f, ((fx - sx) as usize, (fy - sy) as usize), tip, thickness)?;
+    write_line(f, ((fx + sx) as usize, (fy + sy) as usize), tip, thickness)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl fmt::Display for Diagram {
+    fn fmt(self: &Diagram, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.size();
+        writeln!(f, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#, width, height)?;
+        let margin = self.margin();
+        let mut left = margin;
+        let mut bottom = margin;
+        for (part, grid) in self.grids.iter().enumerate() {
+            match self.style {
+                Style::DotsAndBoxes => self.write_dots_and_boxes(f, part, left, margin)?,
+                Style::StringsAndCoins => self.write_strings_and_coins(f, part, left, margin)?,
+            }
+            let (w, h) = self.extent(grid);
+            left += w + self.gap;
+            bottom = bottom.max(margin + h);
+        }
+        for (i, label) in self.labels.iter().enumerate() {
+            writeln!(f, r#"<text x="{}" y="{}">{}</text>"#, margin, bottom + (i + 1) * self.label_height(), escape(label))?;
+        }
+        writeln!(f, "</svg>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::*;
+    use crate::examples::*;
+
+    fn count(svg: &str, needle: &str) -> usize {
+        svg.matches(needle).count()
+    }
+
+    #[test]
+    fn dots_and_boxes() {
+        let mut pos = SimplePosition::new_game(2, 1);
+        let svg = Diagram::new(&pos, Style::DotsAndBoxes).to_string();
+        assert_eq!(true, svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="150" height="100">"#));
+        assert_eq!(6, count(&svg, "<circle"));
+        assert_eq!(0, count(&svg, "<line"));
+
+        for &side in &[Side::Top, Side::Bottom, Side::Left, Side::Right] {
+            pos.make_move(Move::new(0, 0, side));
+        }
+        let svg = Diagram::new(&pos, Style::DotsAndBoxes)
+            .highlight(Move::new(1, 0, Side::Left)).owner(0, 0, 0, 'A').to_string();
+        assert_eq!(4, count(&svg, "<line"));
+        // The highlighted move is recognised in its other form, (0, 0) Right
+        assert_eq!(1, count(&svg, r#"x1="75" y1="25" x2="75" y2="75" stroke="black" stroke-width="3""#));
+        assert_eq!(1, count(&svg, r#"<rect x="25" y="25" width="50" height="50" fill="lightgrey"/>"#));
+        assert_eq!(1, count(&svg, r#"<text x="50" y="55" text-anchor="middle">A</text>"#));
+    }
+
+    #[test]
+    fn strings_and_coins() {
+        // A single coin has four ground strings, each drawn as an arrow of three lines
+        let mut pos = SimplePosition::new_game(1, 1);
+        let svg = Diagram::new(&pos, Style::StringsAndCoins).to_string();
+        assert_eq!(1, count(&svg, "<circle"));
+        assert_eq!(12, count(&svg, "<line"));
+        assert_eq!(true, svg.contains(r#"<line x1="75" y1="75" x2="25" y2="75""#));
+
+        // Captured coins disappear, unless captured by a highlighted move
+        for &side in &[Side::Top, Side::Bottom, Side::Left] {
+            pos.make_move(Move::new(0, 0, side));
+        }
+        pos.make_move(Move::new(0, 0, Side::Right));
+        assert_eq!(0, count(&Diagram::new(&pos, Style::StringsAndCoins).to_string(), "<circle"));
+        let svg = Diagram::new(&pos, Style::StringsAndCoins).highlight(Move::new(0, 0, Side::Right)).to_string();
+        assert_eq!(1, count(&svg, r#"stroke-width="3" fill="white""#));
+        assert_eq!(3, count(&svg, r#"stroke-width="3"/>"#));
+
+        // Two coins joined by a string
+        let svg = Diagram::new(&make_chain(2), Style::StringsAndCoins).to_string();
+        assert_eq!(2, count(&svg, "<circle"));
+        assert_eq!(true, svg.contains(r#"<line x1="125" y1="75" x2="75" y2="75""#));
+    }
+
+    #[test]
+    fn compound_positions() {
+        let pos = CompoundPosition::new(vec!(make_chain(3), double_loop(2)));
+        let svg = Diagram::new(&pos, Style::DotsAndBoxes)
+            .highlight(CPosMove::new(1, 0, 0, Side::Top)).label("Chain & loop").to_string();
+        // Grids of 3x1 and 2x4 boxes side by side, with one label underneath
+        assert_eq!(true, svg.contains(r#"width="350" height="275""#));
+        assert_eq!(8 + 15, count(&svg, "<circle"));
+        assert_eq!(1, count(&svg, r#"<line x1="225" y1="25" x2="275" y2="25" stroke="black" stroke-width="3"/>"#));
+        assert_eq!(true, svg.contains(r#"<text x="25" y="250">Chain &amp; loop</text>"#));
+
+        let svg = Diagram::new(&pos, Style::StringsAndCoins).gap(20).to_string();
+        assert_eq!(3 + 8, count(&svg, "<circle"));
+        assert_eq!(true, svg.contains(r#"width="180" height="120""#));
+    }
+
+    #[test]
+    fn games() {
+        let mut game = Game::new(SimplePosition::new_game(2, 1), "alice", "Bob");
+        for m in vec!(Move::new(0, 0, Side::Top), Move::new(0, 0, Side::Bottom), Move::new(0, 0, Side::Left),
+                      Move::new(0, 0, Side::Right), Move::new(1, 0, Side::Top)) {
+            game.make_move(m).unwrap();
+        }
+        let svg = Diagram::from_game(&game, Style::DotsAndBoxes).to_string();
+        assert_eq!(1, count(&svg, ">B</text>"));
+        assert_eq!(0, count(&svg, ">A</text>"));
+        // Bob's last turn was a capture followed by another move
+        assert_eq!(2, count(&svg, r#"stroke-width="3""#));
+        assert_eq!(true, svg.contains(">alice 0 - 1 Bob</text>"));
+
+        let game = Game::new(CompoundPosition::from_single(SimplePosition::new_game(1, 1)), "A", "B");
+        let svg = Diagram::from_game(&game, Style::StringsAndCoins).to_string();
+        assert_eq!(0, count(&svg, r#"stroke-width="3""#));
+        assert_eq!(true, svg.contains(">A 0 - 0 B</text>"));
+    }
+}
@@ -0,0 +1,439 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use std::fmt;
+use std::mem;
+use std::sync::Mutex;
+
+// Fixed-capacity cache of search results, keyed on Position::canonical_zhash values,
+// which can be kept across searches so that later ones reuse the work of earlier ones.
+// Each key maps to a bucket of BUCKET_SIZE slots. The table starts small and
+// doubles in size whenever it is half full, until it reaches its memory limit; from then on,
+// new entries replace old ones in a full bucket according to the replacement policy.
+
+// Default memory limit for a table
+pub const DEFAULT_MAX_BYTES: usize = 1 << 30;
+
+// Smallest number of slots a table will allocate
+const MIN_CAPACITY: usize = 1 << 10;
+
+const BUCKET_SIZE: usize = 2;
+
+// Shards to split a table into for each thread sharing it, so that two threads
+// seldom want the same shard at once
+pub const SHARDS_PER_THREAD: usize = 16;
+
+// How to choose between an existing entry and a new one for the same slot
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub enum Replacement {
+    // Evict the entry of least depth, i.e. which took least work to compute,
+    // unless the new entry has less depth still
+    DepthPreferred,
+    // Always evict the oldest entry
+    AlwaysReplace,
+}
+
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+struct Slot<V> {
+    key: usize,
+    depth: usize,
+    value: V,
+}
+
+// Counts of how well the table is working
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+pub struct Stats {
+    pub hits: usize,
+    pub misses: usize,
+    // Entries which were evicted to make way for others
+    pub replacements: usize,
+    pub entries: usize,
+    pub capacity: usize,
+    pub max_capacity: usize,
+}
+
+impl Stats {
+    // Fraction of lookups which found an entry
+    pub fn hit_rate(self: &Stats) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(self: &Stats, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} entries (capacity {} of {}), {} hits, {} misses ({:.1}% hit rate), {} replacements",
+               self.entries, self.capacity, self.max_capacity, self.hits, self.misses,
+               100.0 * self.hit_rate(), self.replacements)
+    }
+}
+
+pub struct TranspositionTable<V> {
+    slots: Vec<Option<Slot<V>>>,
+    max_capacity: usize,
+    policy: Replacement,
+    stats: Stats,
+}
+
+impl<V: Copy> TranspositionTable<V> {
+    // Create a table using at most max_bytes of memory for its entries
+    pub fn new(max_bytes: usize, policy: Replacement) -> TranspositionTable<V> {
+        let max_capacity = TranspositionTable::<V>::capacity_for(max_bytes);
+        TranspositionTable{
+            slots: vec!(None; MIN_CAPACITY.min(max_capacity)),
+            max_capacity: max_capacity,
+            policy: policy,
+            stats: Stats::default(),
+        }
+    }
+
+    // Largest power of two number of slots fitting into the given memory
+    fn capacity_for(max_bytes: usize) -> usize {
+        let slots = (max_bytes / mem::size_of::<Option<Slot<V>>>()).max(BUCKET_SIZE);
+        1 << (usize::BITS - 1 - slots.leading_zeros())
+    }
+
+    pub fn policy(self: &TranspositionTable<V>) -> Replacement {
+        self.policy
+    }
+
+    pub fn max_bytes(self: &TranspositionTable<V>) -> usize {
+        self.max_capacity * mem::size_of::<Option<Slot<V>>>()
+    }
+
+    pub fn len(self: &TranspositionTable<V>) -> usize {
+        self.stats.entries
+    }
+
+    pub fn is_empty(self: &TranspositionTable<V>) -> bool {
+        self.stats.entries == 0
+    }
+
+    pub fn stats(self: &TranspositionTable<V>) -> Stats {
+        Stats{capacity: self.slots.len(), max_capacity: self.max_capacity, ..self.stats}
+    }
+
+    // Index of the first slot in the bucket for a key
+    fn bucket(self: &TranspositionTable<V>, key: usize) -> usize {
+        (key & (self.slots.len() / BUCKET_SIZE - 1)) * BUCKET_SIZE
+    }
+
+    // Look up the entry for a key, counting the lookup as a hit or miss
+    pub fn get(self: &mut TranspositionTable<V>, key: usize) -> Option<V> {
+        let b = self.bucket(key);
+        let found = self.slots[b..b + BUCKET_SIZE].iter().flatten().find(|slot| slot.key == key);
+        match found {
+            Some(slot) => {
+                self.stats.hits += 1;
+                Some(slot.value)
+            },
+            None => {
+                self.stats.misses += 1;
+                None
+            },
+        }
+    }
+
+    // Store the value for a key. The depth measures how much work the value
+    // represents, such as the number of moves left in the position.
+    pub fn insert(self: &mut TranspositionTable<V>, key: usize, depth: usize, value: V) {
+        self.store_growing(Slot{key: key, depth: depth, value: value});
+    }
+
+    // Store an entry, first doubling the capacity if the table is half full and may grow
+    fn store_growing(self: &mut TranspositionTable<V>, new: Slot<V>) {
+        if 2 * self.stats.entries >= self.slots.len() && self.slots.len() < self.max_capacity {
+            let capacity = 2 * self.slots.len();
+            self.rebuild(capacity);
+        }
+        self.store(new);
+    }
+
+    fn store(self: &mut TranspositionTable<V>, new: Slot<V>) {
+        let b = self.bucket(new.key);
+        let bucket = &mut self.slots[b..b + BUCKET_SIZE];
+        if let Some(slot) = bucket.iter_mut().find(|s| s.is_some_and(|s| s.key == new.key)) {
+            *slot = Some(new);
+            return;
+        }
+        if let Some(slot) = bucket.iter_mut().find(|s| s.is_none()) {
+            *slot = Some(new);
+            self.stats.entries += 1;
+            return;
+        }
+        // Slots in a bucket are kept in order of insertion, oldest first
+        let victim = match self.policy {
+            Replacement::DepthPreferred => {
+                let (i, shallowest) = bucket.iter().enumerate()
+                    .min_by_key(|(_, s)| s.map_or(0, |s| s.depth)).unwrap();
+                if shallowest.is_some_and(|s| s.depth > new.depth) {
+                    return;
+                }
+                i
+            },
+            Replacement::AlwaysReplace => 0,
+        };
+        bucket[victim..].rotate_left(1);
+        bucket[BUCKET_SIZE - 1] = Some(new);
+        self.stats.replacements += 1;
+    }
+
+    // Move all entries into a table of a different capacity. When growing, every
+    // entry still fits, as each old bucket maps onto two new ones.
+    fn rebuild(self: &mut TranspositionTable<V>, capacity: usize) {
+        let old_slots = mem::replace(&mut self.slots, vec!(None; capacity));
+        self.stats.entries = 0;
+        for slot in old_slots.into_iter().flatten() {
+            self.store(slot);
+        }
+    }
+
+    // Change the memory limit, discarding entries if the table must shrink
+    pub fn set_max_bytes(self: &mut TranspositionTable<V>, max_bytes: usize) {
+        self.max_capacity = TranspositionTable::<V>::capacity_for(max_bytes);
+        if self.slots.len() > self.max_capacity {
+            let capacity = self.max_capacity;
+            self.rebuild(capacity);
+        }
+    }
+
+    // Remove all entries and reset the statistics
+    pub fn clear(self: &mut TranspositionTable<V>) {
+        self.slots = vec!(None; MIN_CAPACITY.min(self.max_capacity));
+        self.stats = Stats::default();
+    }
+}
+
+impl<V: Copy> Default for TranspositionTable<V> {
+    fn default() -> TranspositionTable<V> {
+        TranspositionTable::new(DEFAULT_MAX_BYTES, Replacement::DepthPreferred)
+    }
+}
+
+// Access to a table by a search, which may be running alone or alongside others
+pub trait Cache<V> {
+    fn get(&mut self, key: usize) -> Option<V>;
+    fn insert(&mut self, key: usize, depth: usize, value: V);
+}
+
+impl<V: Copy> Cache<V> for TranspositionTable<V> {
+    fn get(self: &mut TranspositionTable<V>, key: usize) -> Option<V> {
+        TranspositionTable::get(self, key)
+    }
+
+    fn insert(self: &mut TranspositionTable<V>, key: usize, depth: usize, value: V) {
+        TranspositionTable::insert(self, key, depth, value)
+    }
+}
+
+// A table shared between threads, split by key into shards, each with its own lock,
+// so that threads rarely have to wait for one another. The shards are chosen by the
+// high bits of the key, as the low bits choose the bucket within a shard.
+pub struct ShardedTable<V> {
+    shards: Vec<Mutex<TranspositionTable<V>>>,
+    max_capacity: usize,
+    policy: Replacement,
+    // Counts carried over from the table the shards were made from
+    stats: Stats,
+}
+
+fn shard_index(key: usize, shards: usize) -> usize {
+    (key >> (usize::BITS / 2)) % shards
+}
+
+impl<V: Copy> ShardedTable<V> {
+    // Share the entries of a table out between a given number of shards,
+    // which between them have the same memory limit
+    pub fn new(table: TranspositionTable<V>, shards: usize) -> ShardedTable<V> {
+        let shards = shards.clamp(1, (table.max_capacity / MIN_CAPACITY).max(1));
+        let max_bytes = table.max_bytes() / shards;
+        let mut parts: Vec<TranspositionTable<V>> = (0..shards).map(|_| TranspositionTable::new(max_bytes, table.policy)).collect();
+        for slot in table.slots.into_iter().flatten() {
+            parts[shard_index(slot.key, shards)].store_growing(slot);
+        }
+        ShardedTable{
+            shards: parts.into_iter().map(Mutex::new).collect(),
+            max_capacity: table.max_capacity,
+            policy: table.policy,
+            stats: table.stats,
+        }
+    }
+
+    fn shard(self: &ShardedTable<V>, key: usize) -> &Mutex<TranspositionTable<V>> {
+        &self.shards[shard_index(key, self.shards.len())]
+    }
+
+    // Gather the entries back into a single table, adding up the statistics
+    pub fn into_table(self: ShardedTable<V>) -> TranspositionTable<V> {
+        let mut table = TranspositionTable{
+            slots: vec!(None; MIN_CAPACITY.min(self.max_capacity)),
+            max_capacity: self.max_capacity,
+            policy: self.policy,
+            stats: self.stats,
+        };
+        table.stats.entries = 0;
+        for shard in self.shards {
+            let shard = shard.into_inner().unwrap();
+            table.stats.hits += shard.stats.hits;
+            table.stats.misses += shard.stats.misses;
+            table.stats.replacements += shard.stats.replacements;
+            for slot in shard.slots.into_iter().flatten() {
+                table.store_growing(slot);
+            }
+        }
+        table
+    }
+}
+
+impl<V: Copy> Cache<V> for &ShardedTable<V> {
+    fn get(&mut self, key: usize) -> Option<V> {
+        self.shard(key).lock().unwrap().get(key)
+    }
+
+    fn insert(&mut self, key: usize, depth: usize, value: V) {
+        self.shard(key).lock().unwrap().insert(key, depth, value)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ttable::*;
+
+    // Keys which all map to the same slot of a small table
+    fn colliding_key(i: usize) -> usize {
+        i << 20
+    }
+
+    #[test]
+    fn get_and_insert() {
+        let mut table: TranspositionTable<isize> = TranspositionTable::default();
+        assert_eq!(true, table.is_empty());
+        assert_eq!(None, table.get(123));
+        table.insert(123, 5, -3);
+        assert_eq!(Some(-3), table.get(123));
+        table.insert(123, 1, 4);
+        assert_eq!(Some(4), table.get(123));
+        assert_eq!(1, table.len());
+        let stats = table.stats();
+        assert_eq!((2, 1, 0), (stats.hits, stats.misses, stats.replacements));
+        assert_eq!(2.0 / 3.0, stats.hit_rate());
+    }
+
+    #[test]
+    fn grows_to_memory_limit() {
+        let mut table: TranspositionTable<usize> = TranspositionTable::default();
+        for i in 0..100000 {
+            table.insert(i, 0, i);
+        }
+        assert_eq!(100000, table.len());
+        assert_eq!(0, table.stats().replacements);
+        assert_eq!(true, (0..100000).all(|i| table.get(i) == Some(i)));
+
+        let max_bytes = 1 << 16;
+        let mut table: TranspositionTable<usize> = TranspositionTable::new(max_bytes, Replacement::AlwaysReplace);
+        for i in 0..100000 {
+            table.insert(i, 0, i);
+        }
+        assert_eq!(true, table.max_bytes() <= max_bytes);
+        assert_eq!(table.stats().max_capacity, table.len());
+        assert_eq!(Some(99999), table.get(99999));
+        assert_eq!(None, table.get(0));
+    }
+
+    #[test]
+    fn replacement_policies() {
+        let mut table = TranspositionTable::new(1 << 12, Replacement::DepthPreferred);
+        table.insert(colliding_key(1), 10, 1);
+        table.insert(colliding_key(2), 5, 2);
+        table.insert(colliding_key(3), 3, 3);
+        assert_eq!(None, table.get(colliding_key(3)));
+        table.insert(colliding_key(4), 7, 4);
+        assert_eq!(Some(1), table.get(colliding_key(1)));
+        assert_eq!(None, table.get(colliding_key(2)));
+        assert_eq!(Some(4), table.get(colliding_key(4)));
+        assert_eq!(1, table.stats().replacements);
+
+        let mut table = TranspositionTable::new(1 << 12, Replacement::AlwaysReplace);
+        table.insert(colliding_key(1), 10, 1);
+        table.insert(colliding_key(2), 5, 2);
+        table.insert(colliding_key(3), 3, 3);
+        table.insert(colliding_key(4), 7, 4);
+        assert_eq!(None, table.get(colliding_key(1)));
+        assert_eq!(None, table.get(colliding_key(2)));
+        assert_eq!(Some(3), table.get(colliding_key(3)));
+        assert_eq!(Some(4), table.get(colliding_key(4)));
+        assert_eq!(2, table.stats().replacements);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        // Keys spread over every shard but the first, which the threads fill
+        let old_key = |i: usize| ((i % 7 + 1) << 32) + i;
+        let mut table = TranspositionTable::default();
+        for i in 0..1000 {
+            table.insert(old_key(i), 0, i);
+        }
+        assert_eq!(Some(999), table.get(old_key(999)));
+        let table = ShardedTable::new(table, 8);
+        std::thread::scope(|scope| {
+            for t in 0..4 {
+                let mut cache = &table;
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        cache.insert(4 * i + t, 0, i);
+                    }
+                    // Entries from before the table was shared can still be found
+                    assert_eq!(Some(t), cache.get(old_key(t)));
+                });
+            }
+        });
+        // Every insertion either added an entry or replaced one
+        let mut table = table.into_table();
+        assert_eq!(5000, table.len() + table.stats().replacements);
+        assert_eq!((5, 0), (table.stats().hits, table.stats().misses));
+        assert_eq!(true, (0..4000).all(|k| table.get(k).is_none_or(|i| 4 * i + k % 4 == k)));
+        assert_eq!(true, (0..1000).all(|i| table.get(old_key(i)) == Some(i)));
+        assert_eq!(DEFAULT_MAX_BYTES, table.max_bytes());
+    }
+
+    #[test]
+    fn shrink_and_clear() {
+        let mut table = TranspositionTable::default();
+        for i in 0..10000 {
+            table.insert(i, i, i);
+        }
+        table.set_max_bytes(1 << 12);
+        assert_eq!(true, table.len() <= table.stats().max_capacity);
+        assert_eq!(true, table.max_bytes() <= 1 << 12);
+        table.clear();
+        assert_eq!(true, table.is_empty());
+        assert_eq!(Stats::default().hits, table.stats().hits);
+    }
+}
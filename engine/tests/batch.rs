@@ -0,0 +1,79 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run_batch(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dabengine"))
+        .arg("batch")
+        .args(args)
+        .output()
+        .expect("Could not start engine")
+}
+
+fn example(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(name).to_string_lossy().into_owned()
+}
+
+// Lines of CSV output, without the timings, which vary from run to run
+fn csv_rows(output: &Output) -> Vec<String> {
+    String::from_utf8(output.stdout.clone()).unwrap().lines()
+        .map(|l| l.rsplit_once(',').unwrap().0.to_string()).collect()
+}
+
+#[test]
+fn example_positions() {
+    let (p50bl, ex5p1) = (example("p50bl.pos"), example("ex5p1.pos"));
+    let output = run_batch(&["--csv", &p50bl, &ex5p1]);
+    assert_eq!(true, output.status.success());
+    assert_eq!(vec!("file,size,value,best_move,nimstring_value".to_string(),
+                    format!("{},3x2,-4,0 1 l,*4", p50bl),
+                    format!("{},3x3,3,2 1 b,*2", ex5p1)),
+               csv_rows(&output));
+
+    let output = run_batch(&[&p50bl]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(2, lines.len());
+    assert_eq!(true, lines[0].starts_with("File") && lines[0].ends_with("Seconds"));
+    assert_eq!(true, lines[1].starts_with(&p50bl) && lines[1].contains(" -4  0 1 l "));
+}
+
+#[test]
+fn parse_errors() {
+    let dir = std::env::temp_dir().join(format!("dabengine-batch-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("bad.pos"), "3 3\n9 9 t\n").unwrap();
+    fs::write(dir.join("good.pos"), "1 1\n0 0 t\n").unwrap();
+    // Commands other than moves are not run, so cannot spoil the output
+    fs::write(dir.join("eval.pos"), "2 2\n0 0 t\neval\n").unwrap();
+    let output = run_batch(&["--csv", &dir.to_string_lossy()]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The other files are still analysed
+    assert_eq!(Some(1), output.status.code());
+    let good = dir.join("good.pos").to_string_lossy().into_owned();
+    assert_eq!(vec!("file,size,value,best_move,nimstring_value".to_string(), format!("{},1x1,1,0 0 l,*0", good)),
+               csv_rows(&output));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(true, stderr.contains("bad.pos: Line 2: Not a legal move"), "{}", stderr);
+    assert_eq!(true, stderr.contains("eval.pos: Line 3: Only moves, undo and redo are allowed here"), "{}", stderr);
+    assert_eq!(true, stderr.contains("2 file(s) could not be analysed"), "{}", stderr);
+}
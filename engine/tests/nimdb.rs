@@ -0,0 +1,61 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn run_nimdb(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dabengine"))
+        .arg("nimdb")
+        .args(args)
+        .output()
+        .expect("Could not start engine")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn build_and_query() {
+    let db = std::env::temp_dir().join(format!("dabengine-nimdb-test-{}.db", std::process::id()));
+    let db = db.to_string_lossy().into_owned();
+    let output = run_nimdb(&["build", "4", &db]);
+    assert_eq!(true, output.status.success());
+    assert_eq!("Added 305 fragments, making 305 in total\n", stdout(&output));
+
+    // Building further only adds what is missing
+    let output = run_nimdb(&["build", "6", &db]);
+    assert_eq!("Added 9187 fragments, making 9492 in total\n", stdout(&output));
+
+    let output = run_nimdb(&["query", &db, "1x3:h/g/1"]);
+    assert_eq!("1x3:h/g/1 *0\n", stdout(&output));
+    let p50bl = Path::new(env!("CARGO_MANIFEST_DIR")).join("p50bl.pos");
+    let output = run_nimdb(&["query", &db, &p50bl.to_string_lossy()]);
+    assert_eq!("2x3:oh/gh/92 *4\n", stdout(&output));
+
+    // A damaged database is rejected
+    let text = fs::read_to_string(&db).unwrap();
+    fs::write(&db, text.replacen(" *0\n", " *1\n", 1)).unwrap();
+    let output = run_nimdb(&["query", &db, "1x3:h/g/1"]);
+    fs::remove_file(&db).unwrap();
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(true, stderr.contains("Database is corrupt"), "{}", stderr);
+}
@@ -0,0 +1,137 @@
+/*
+    Copyright 2026 Andrew Medworth <github@medworth.org.uk>
+
+    This file is part of Dots-and-Boxes Engine.
+
+    Dots-and-Boxes Engine is free software: you can redistribute it and/or modify
+    it under the terms of the GNU Affero General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Dots-and-Boxes Engine is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU Affero General Public License for more details.
+
+    You should have received a copy of the GNU Affero General Public License
+    along with Dots-and-Boxes Engine.  If not, see <http://www.gnu.org/licenses/>.
+*/
+use dabengine::eval;
+use dabengine::game::{Position, SimplePosition};
+use dabengine::protocol;
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+// The engine running in protocol mode, as a GUI would drive it
+struct Engine {
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+impl Engine {
+    fn start() -> Engine {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_dabengine"))
+            .arg("protocol")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Could not start engine");
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        Engine{child: child, input: input, output: output}
+    }
+
+    fn send(&mut self, command: &str) {
+        writeln!(self.input, "{}", command).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> String {
+        let mut line = String::new();
+        self.output.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+
+    // Read lines up to and including the bestmove line
+    fn receive_until_bestmove(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.receive();
+            assert!(!line.is_empty(), "Engine stopped responding after {:?}", lines);
+            let done = line.starts_with("bestmove");
+            lines.push(line);
+            if done {
+                return lines;
+            }
+        }
+    }
+}
+
+#[test]
+fn search_and_report() {
+    let mut engine = Engine::start();
+    engine.send("isready");
+    assert_eq!("readyok", engine.receive());
+
+    engine.send("newgame 2 2");
+    engine.send("position 0,0,t 1,0,t");
+    engine.send("go movetime 60000");
+    let lines = engine.receive_until_bestmove();
+
+    let mut pos = SimplePosition::new_game(2, 2);
+    pos.make_move(protocol::parse_move("0,0,t").unwrap());
+    pos.make_move(protocol::parse_move("1,0,t").unwrap());
+    let (val, _) = eval::eval(&pos);
+    assert_eq!(2, lines.len());
+    let info: Vec<&str> = lines[0].split(' ').collect();
+    assert_eq!(["info", "value", &val.to_string(), "pv"], info[..4]);
+    let best_move = protocol::parse_move(lines[1].strip_prefix("bestmove ").unwrap()).unwrap();
+    assert_eq!(info[4], protocol::format_move(best_move));
+    // The principal variation plays out the rest of the game
+    for m in info[4..].iter() {
+        pos.try_make_move(protocol::parse_move(m).unwrap()).unwrap();
+    }
+    assert!(pos.is_end_of_game());
+
+    engine.send("quit");
+    assert!(engine.child.wait().unwrap().success());
+}
+
+#[test]
+fn stop_and_time_limit() {
+    let mut engine = Engine::start();
+    // Far too big to solve, so the search only ends when told to
+    engine.send("newgame 5 5");
+    engine.send("go");
+    engine.send("stop");
+    let lines = engine.receive_until_bestmove();
+    assert_eq!(1, lines.len());
+    let best_move = protocol::parse_move(lines[0].strip_prefix("bestmove ").unwrap()).unwrap();
+    assert!(SimplePosition::new_game(5, 5).is_legal_move(best_move));
+
+    engine.send("go movetime 100");
+    assert!(engine.receive_until_bestmove()[0].starts_with("bestmove "));
+
+    engine.send("quit");
+    assert!(engine.child.wait().unwrap().success());
+}
+
+#[test]
+fn errors_and_game_over() {
+    let mut engine = Engine::start();
+    engine.send("newgame 1 1");
+    engine.send("position 0,0,t 0,0,t");
+    assert!(engine.receive().starts_with("info string Not a legal move"));
+    engine.send("fly");
+    assert_eq!("info string Unknown command [fly]", engine.receive());
+
+    engine.send("position 0,0,t 0,0,l 0,0,b 0,0,r");
+    engine.send("go");
+    assert_eq!(vec!("bestmove none"), engine.receive_until_bestmove());
+
+    // Closing the input ends the session
+    drop(engine.input);
+    assert!(engine.child.wait().unwrap().success());
+}